@@ -0,0 +1,293 @@
+use super::Kernel;
+use anyhow::Result;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::sync::Arc;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::task::spawn_blocking;
+
+/// 管道缓冲区长度
+const BUFFER_SIZE: usize = 64 * 1024;
+
+/// 管道缓冲区数量
+const BUFFER_COUNT: usize = 16;
+
+/// 异步存储核心
+///
+/// 所有轨道读写都在阻塞线程池中执行，
+/// 异步流和阻塞线程之间通过管道传递数据，
+/// 所以不会阻塞异步运行时
+#[derive(Clone)]
+pub struct AsyncKernel {
+    kernel: Arc<Kernel>,
+}
+
+impl AsyncKernel {
+    /// 创建实例
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{AsyncKernel, Kernel};
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let kernel = AsyncKernel::new(kernel);
+    /// ```
+    pub fn new(kernel: Kernel) -> Self {
+        Self {
+            kernel: Arc::new(kernel),
+        }
+    }
+
+    /// 获取同步实例
+    ///
+    /// 同步实例的调用会阻塞当前线程，
+    /// 在异步上下文中使用的时候需要自行放入阻塞线程池
+    pub fn kernel(&self) -> &Arc<Kernel> {
+        &self.kernel
+    }
+
+    /// 读取数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{AsyncKernel, Kernel};
+    ///
+    /// let kernel = AsyncKernel::new(Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap());
+    ///
+    /// let file = tokio::fs::File::create("test.mp4").await.unwrap();
+    /// kernel.read(b"test", file).await.unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub async fn read(&self, key: &[u8], mut stream: impl AsyncWrite + Unpin) -> Result<()> {
+        let (sender, mut receiver) = channel(BUFFER_COUNT);
+        let kernel = self.kernel.clone();
+        let key = key.to_vec();
+        let handle = spawn_blocking(move || {
+            kernel.read(&key, Pipe::writer(sender))
+        });
+
+        // 将阻塞线程读取的数据写入外部流，
+        // 外部流写入失败的时候关闭管道，
+        // 阻塞线程的读取随之失败
+        while let Some(data) = receiver.recv().await {
+            if let Err(e) = stream.write_all(&data).await {
+                drop(receiver);
+                let _ = handle.await;
+                return Err(e.into());
+            }
+        }
+
+        handle.await??;
+        stream.flush().await?;
+        Ok(())
+    }
+
+    /// 写入数据
+    ///
+    /// 键已经存在的时候返回错误
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{AsyncKernel, Kernel};
+    ///
+    /// let kernel = AsyncKernel::new(Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap());
+    ///
+    /// let file = tokio::fs::File::open("test.mp4").await.unwrap();
+    /// kernel.write(b"test", file).await.unwrap();
+    /// ```
+    pub async fn write(&self, key: &[u8], stream: impl AsyncRead + Unpin) -> Result<()> {
+        self.store(key, stream, false).await
+    }
+
+    /// 写入或者替换数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{AsyncKernel, Kernel};
+    ///
+    /// let kernel = AsyncKernel::new(Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap());
+    ///
+    /// let file = tokio::fs::File::open("test.mp4").await.unwrap();
+    /// kernel.put(b"test", file).await.unwrap();
+    /// ```
+    pub async fn put(&self, key: &[u8], stream: impl AsyncRead + Unpin) -> Result<()> {
+        self.store(key, stream, true).await
+    }
+
+    /// 删除数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{AsyncKernel, Kernel};
+    ///
+    /// let kernel = AsyncKernel::new(Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap());
+    ///
+    /// kernel.delete(b"test").await.unwrap();
+    /// ```
+    pub async fn delete(&self, key: &[u8]) -> Result<()> {
+        let kernel = self.kernel.clone();
+        let key = key.to_vec();
+        spawn_blocking(move || kernel.delete(&key)).await?
+    }
+
+    /// 将外部流写入阻塞线程
+    ///
+    /// 外部流读取失败的时候将错误传递给阻塞线程，
+    /// 阻塞线程的写入随之失败并释放已经分配的分片，
+    /// 外部流结束的时候发送结束标记，
+    /// 没有收到结束标记管道就已经关闭的时候，
+    /// 比如调用方的任务被取消，
+    /// 阻塞线程同样写入失败而不会保存不完整的数据
+    #[rustfmt::skip]
+    async fn store(
+        &self,
+        key: &[u8],
+        mut stream: impl AsyncRead + Unpin,
+        replace: bool
+    ) -> Result<()> {
+        let (sender, receiver) = channel(BUFFER_COUNT);
+        let kernel = self.kernel.clone();
+        let key = key.to_vec();
+        let handle = spawn_blocking(move || {
+            let pipe = Pipe::reader(receiver);
+            match replace {
+                true => kernel.put(&key, pipe),
+                false => kernel.write(&key, pipe)
+            }
+        });
+
+        // 阻塞线程提前结束的时候管道已经关闭，
+        // 这时候停止读取外部流
+    loop {
+        let mut buffer = vec![0u8; BUFFER_SIZE];
+        let message = match stream.read(&mut buffer).await {
+            Ok(0) => None,
+            Ok(size) => {
+                buffer.truncate(size);
+                Some(Ok(buffer))
+            },
+            Err(e) => Some(Err(e))
+        };
+
+        let done = !matches!(message, Some(Ok(_)));
+        if sender.send(message).await.is_err() || done {
+            break;
+        }
+    }
+
+        drop(sender);
+        handle.await?
+    }
+}
+
+/// 同步管道
+///
+/// 在阻塞线程中实现`Read`和`Write`，
+/// 数据通过异步管道和运行时交换，
+/// 读取端以`None`作为结束标记
+struct Pipe<T> {
+    buffer: Vec<u8>,
+    cursor: usize,
+    done: bool,
+    inner: T,
+}
+
+/// 读取端消息
+///
+/// `None`表示外部流已经结束
+type Message = Option<std::io::Result<Vec<u8>>>;
+
+impl Pipe<Receiver<Message>> {
+    fn reader(receiver: Receiver<Message>) -> Self {
+        Self {
+            buffer: Vec::new(),
+            inner: receiver,
+            done: false,
+            cursor: 0,
+        }
+    }
+}
+
+impl Pipe<Sender<Vec<u8>>> {
+    fn writer(sender: Sender<Vec<u8>>) -> Self {
+        Self {
+            buffer: Vec::with_capacity(BUFFER_SIZE),
+            inner: sender,
+            done: false,
+            cursor: 0,
+        }
+    }
+}
+
+impl Read for Pipe<Receiver<Message>> {
+    #[rustfmt::skip]
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+
+        // 当前缓冲区已经读取完成，
+        // 从管道中接收下个缓冲区，
+        // 没有收到结束标记管道就已经关闭说明数据不完整
+    while self.cursor >= self.buffer.len() {
+        if self.done {
+            return Ok(0);
+        }
+
+        match self.inner.blocking_recv() {
+            None => return Err(Error::new(ErrorKind::UnexpectedEof, "stream cancelled")),
+            Some(None) => self.done = true,
+            Some(Some(message)) => {
+                self.buffer = message?;
+                self.cursor = 0;
+            }
+        }
+    }
+
+        let size = std::cmp::min(buf.len(), self.buffer.len() - self.cursor);
+        buf[..size].copy_from_slice(&self.buffer[self.cursor..self.cursor + size]);
+        self.cursor += size;
+        Ok(size)
+    }
+}
+
+impl Write for Pipe<Sender<Vec<u8>>> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= BUFFER_SIZE {
+            self.flush()?;
+        }
+
+        Ok(buf.len())
+    }
+
+    #[rustfmt::skip]
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+
+        let buffer = std::mem::replace(&mut self.buffer, Vec::with_capacity(BUFFER_SIZE));
+        self.inner.blocking_send(buffer).map_err(|_| {
+            Error::new(ErrorKind::BrokenPipe, "pipe closed")
+        })
+    }
+}
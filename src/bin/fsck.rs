@@ -0,0 +1,57 @@
+use physeter::{Kernel, KernelOptions};
+use std::process::exit;
+
+/// 检查存储目录
+///
+/// physeter-fsck <path> <track_size> [--repair]
+///
+/// 存储目录有超级块的时候使用超级块中的格式参数，
+/// 否则按照旧版本格式读取，
+/// 检查模式以只读方式打开，不会恢复未完成的操作，
+/// 修复模式先恢复未完成的操作再重建失效链表
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let repair = args.iter().any(|x| x == "--repair");
+    let params: Vec<&String> = args.iter().filter(|x| !x.starts_with("--")).collect();
+    if params.len() != 2 {
+        eprintln!("usage: physeter-fsck <path> <track_size> [--repair]");
+        exit(2);
+    }
+
+    let mut options = KernelOptions::load(params[0].clone(), params[1].parse()?)?;
+    options.read_only = !repair;
+
+    let kernel = Kernel::with_options(options)?;
+    let report = kernel.fsck(repair)?;
+
+    println!("tracks: {}", report.tracks);
+    println!("chunks: {}", report.chunks);
+    println!("referenced: {}", report.referenced);
+    println!("free: {}", report.free);
+
+    for (track, offset) in report.orphans.iter() {
+        println!("orphan: track {} offset {}", track, offset);
+    }
+
+    for (track, offset) in report.double_allocated.iter() {
+        println!("double allocated: track {} offset {}", track, offset);
+    }
+
+    for damage in report.invalid.iter() {
+        println!("invalid: {} {}", String::from_utf8_lossy(&damage.key), damage.reason);
+    }
+
+    for (track, error) in report.track_errors.iter() {
+        println!("track {}: {}", track, error);
+    }
+
+    if report.repaired {
+        println!("repaired");
+    }
+
+    if !report.is_clean() && !report.repaired {
+        exit(1);
+    }
+
+    Ok(())
+}
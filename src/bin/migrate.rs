@@ -0,0 +1,43 @@
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::process::exit;
+
+/// 迁移存储目录
+///
+/// physeter-migrate <source> <target> <track_size> [--crc32c]
+///
+/// 源目录有超级块的时候使用超级块中的格式参数，
+/// 否则按照旧版本格式读取，
+/// 源目录以只读方式打开，迁移不会修改源目录
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let crc32c = args.iter().any(|x| x == "--crc32c");
+    let params: Vec<&String> = args.iter().filter(|x| !x.starts_with("--")).collect();
+    if params.len() != 3 {
+        eprintln!("usage: physeter-migrate <source> <target> <track_size> [--crc32c]");
+        exit(2);
+    }
+
+    let track_size = params[2].parse()?;
+    let mut source_options = KernelOptions::load(params[0].clone(), track_size)?;
+    source_options.read_only = true;
+
+    let mut target_options = KernelOptions::from(params[1].clone(), track_size);
+    if crc32c {
+        target_options.chunk_format = ChunkFormat::Crc32c;
+    }
+
+    std::fs::create_dir_all(params[1])?;
+    let source = Kernel::with_options(source_options)?;
+    let target = Kernel::with_options(target_options)?;
+    let report = source.migrate(&target, |report| {
+        let count = report.objects + report.skipped;
+        if count % 1000 == 0 {
+            println!("processed: {} bytes: {}", count, report.bytes);
+        }
+    })?;
+
+    println!("migrated: {}", report.objects);
+    println!("skipped: {}", report.skipped);
+    println!("bytes: {}", report.bytes);
+    Ok(())
+}
@@ -0,0 +1,135 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+/// 缓存键
+///
+/// 轨道ID和分片位置
+type Key = (u16, u64);
+
+/// 缓存状态
+///
+/// `entries` 分片数据以及最近访问序号
+/// `order` 按照访问序号排列的缓存键
+/// `tick` 当前访问序号
+#[derive(Default)]
+struct State {
+    entries: HashMap<Key, (u64, Arc<Vec<u8>>)>,
+    order: BTreeMap<u64, Key>,
+    tick: u64,
+}
+
+/// 分片缓存
+///
+/// 缓存已经通过校验的完整分片，
+/// 缓存已满的时候淘汰最久没有访问的分片，
+/// 直接读写的时候用来代替操作系统页缓存
+pub struct Cache {
+    state: Mutex<State>,
+    capacity: usize,
+}
+
+impl Cache {
+    /// 创建缓存
+    ///
+    /// `capacity` 最大缓存分片数量
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Cache;
+    ///
+    /// let cache = Cache::new(1024);
+    /// ```
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(State::default()),
+            capacity,
+        }
+    }
+
+    /// 获取分片
+    ///
+    /// 命中的时候更新分片的访问序号
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Cache;
+    ///
+    /// let cache = Cache::new(1024);
+    /// let chunk = cache.get(1, 8192);
+    /// ```
+    #[rustfmt::skip]
+    pub fn get(&self, track: u16, offset: u64) -> Option<Arc<Vec<u8>>> {
+        let mut state = self.state.lock().ok()?;
+        state.tick += 1;
+        let tick = state.tick;
+        let (last, chunk) = match state.entries.get_mut(&(track, offset)) {
+            Some(entry) => (std::mem::replace(&mut entry.0, tick), entry.1.clone()),
+            None => return None
+        };
+
+        state.order.remove(&last);
+        state.order.insert(tick, (track, offset));
+        Some(chunk)
+    }
+
+    /// 写入分片
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Cache;
+    ///
+    /// let cache = Cache::new(1024);
+    /// cache.insert(1, 8192, &[0u8; 4096]);
+    /// ```
+    #[rustfmt::skip]
+    pub fn insert(&self, track: u16, offset: u64, chunk: &[u8]) {
+        let mut state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return
+        };
+
+        state.tick += 1;
+        let tick = state.tick;
+        let value = (tick, Arc::new(chunk.to_vec()));
+        if let Some((last, _)) = state.entries.insert((track, offset), value) {
+            state.order.remove(&last);
+        }
+
+        state.order.insert(tick, (track, offset));
+
+        // 超出容量的时候
+        // 淘汰最久没有访问的分片
+    while state.entries.len() > self.capacity {
+        let key = match state.order.iter().next() {
+            Some((tick, key)) => (*tick, *key),
+            None => break
+        };
+
+        state.order.remove(&key.0);
+        state.entries.remove(&key.1);
+    }
+    }
+
+    /// 删除分片
+    ///
+    /// 分片被重写或者释放的时候调用
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Cache;
+    ///
+    /// let cache = Cache::new(1024);
+    /// cache.remove(1, 8192);
+    /// ```
+    pub fn remove(&self, track: u16, offset: u64) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some((tick, _)) = state.entries.remove(&(track, offset)) {
+                state.order.remove(&tick);
+            }
+        }
+    }
+}
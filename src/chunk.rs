@@ -1,24 +1,74 @@
 use super::KernelOptions;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::fmt;
 use bytes::{
     BufMut, 
     Bytes, 
     BytesMut
 };
 
+/// 分片格式
+///
+/// `Plain` 分片头为下个分片位置和数据长度，共10个字节  
+/// `Crc32c` 在分片头后追加数据校验码，共14个字节
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkFormat {
+    Plain,
+    Crc32c,
+}
+
+/// 分片损坏
+///
+/// 分片校验失败的时候返回该错误，
+/// 包含分片所在的轨道和位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Corruption {
+    pub track: u16,
+    pub offset: u64,
+}
+
 /// 分片编解码器
 ///
 /// 将分片编码为缓冲区
 /// 或者将缓冲区解码为分片.
 ///
 /// #### diff_size
-/// 分片内部最大数据长度，分片固定头长度为17，
-/// 所以这里使用分片长度减去17.
+/// 分片内部最大数据长度，分片固定头长度由分片格式决定，
+/// 所以这里使用分片长度减去分片头长度.
 pub struct Codec {
+    format: ChunkFormat,
+    header_size: usize,
     chunk_size: usize,
     diff_size: usize,
 }
 
+impl ChunkFormat {
+    /// 分片头长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::ChunkFormat;
+    ///
+    /// assert_eq!(ChunkFormat::Plain.header_size(), 10);
+    /// assert_eq!(ChunkFormat::Crc32c.header_size(), 14);
+    /// ```
+    pub fn header_size(self) -> u64 {
+        match self {
+            ChunkFormat::Plain => 10,
+            ChunkFormat::Crc32c => 14,
+        }
+    }
+}
+
+impl fmt::Display for Corruption {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "chunk corrupted: track {} offset {}", self.track, self.offset)
+    }
+}
+
+impl std::error::Error for Corruption {}
+
 impl Codec {
     /// 创建编解码器
     ///
@@ -26,19 +76,21 @@ impl Codec {
     ///
     /// ```no_run
     /// use super::{Codec, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// Codec::new(Rc::new(options));
+    /// Codec::new(Arc::new(options));
     /// ````
-    pub fn new(options: Rc<KernelOptions>) -> Self {
+    pub fn new(options: Arc<KernelOptions>) -> Self {
         Self {
-            diff_size: (options.chunk_size - 10) as usize,
+            header_size: options.chunk_format.header_size() as usize,
+            diff_size: options.diff_size() as usize,
             chunk_size: options.chunk_size as usize,
+            format: options.chunk_format,
         }
     }
 
@@ -48,7 +100,7 @@ impl Codec {
     ///
     /// ```no_run
     /// use super::{Chunk, Codec, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// use bytes::Bytes;
     ///
     /// let chunk = Chunk {
@@ -56,7 +108,7 @@ impl Codec {
     ///     data: Bytes::from_static(b"hello"),
     /// };
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -80,6 +132,13 @@ impl Codec {
 
         packet.put_u64(next);
         packet.put_u16(size);
+        
+        // 校验码覆盖分片头和数据
+        if self.format == ChunkFormat::Crc32c {
+            let crc = crc32c::crc32c_append(crc32c::crc32c(&packet), chunk);
+            packet.put_u32(crc);
+        }
+        
         packet.extend_from_slice(&chunk);
 
         if packet.len() < self.chunk_size {
@@ -95,7 +154,7 @@ impl Codec {
     ///
     /// ```no_run
     /// use super::{Chunk, Codec, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// use bytes::Bytes;
     ///
     /// let chunk = Chunk {
@@ -103,7 +162,7 @@ impl Codec {
     ///     data: Bytes::from_static(b"hello"),
     /// };
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -117,7 +176,7 @@ impl Codec {
     /// ```
     #[rustfmt::skip]
     pub fn decoder<'a>(&self, chunk: &'a [u8]) -> (Option<u64>, &'a [u8]) {
-        assert!(chunk.len() > 10);
+        assert!(chunk.len() > self.header_size);
         let source_next = u64::from_be_bytes([
             chunk[0],
             chunk[1],
@@ -137,10 +196,10 @@ impl Codec {
         let end_offset = match source_size {
             0 => self.diff_size,
             _ => source_size,
-        } + 10;
+        } + self.header_size;
 
         assert!(end_offset <= chunk.len());
-        let data = &chunk[10..end_offset];
+        let data = &chunk[self.header_size..end_offset];
 
         let next = match source_next == 0 {
             false => Some(source_next),
@@ -152,4 +211,54 @@ impl Codec {
             data
         )
     }
+
+    /// 校验分片
+    ///
+    /// 检查分片头中的数据长度是否合法，
+    /// 如果分片格式带有校验码则同时检查校验码
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Codec, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let codec = Codec::new(options);
+    /// let packet = codec.encoder(None, b"hello");
+    /// assert!(codec.verify(&packet));
+    /// ```
+    #[rustfmt::skip]
+    pub fn verify(&self, chunk: &[u8]) -> bool {
+        if chunk.len() < self.chunk_size {
+            return false;
+        }
+
+        let size = match u16::from_be_bytes([chunk[8], chunk[9]]) as usize {
+            0 => self.diff_size,
+            x => x
+        };
+
+        if size > self.diff_size {
+            return false;
+        }
+
+        if self.format == ChunkFormat::Plain {
+            return true;
+        }
+
+        let source_crc = u32::from_be_bytes([
+            chunk[10], 
+            chunk[11], 
+            chunk[12], 
+            chunk[13]
+        ]);
+
+        let data = &chunk[self.header_size..self.header_size + size];
+        crc32c::crc32c_append(crc32c::crc32c(&chunk[..10]), data) == source_crc
+    }
 }
@@ -0,0 +1,205 @@
+use super::{lock, KernelOptions, Tracks};
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::thread::{self, ThreadId};
+use std::sync::{Arc, Mutex};
+
+/// 线程亲和记录的最大数量
+///
+/// 超过之后清空记录，
+/// 避免不断创建的线程让记录无限增长
+const AFFINITY_LIMIT: usize = 1024;
+
+/// 轨道分配策略
+///
+/// `Sequential` 从最小的轨道开始分配
+/// `RoundRobin` 依次轮流分配轨道
+/// `LeastFull` 分配剩余空间最多的轨道
+/// `Affinity` 同一个线程优先分配上次使用的轨道
+///
+/// 写入流在写入期间租用轨道，
+/// 其他写入流不会分配到已经租用的轨道，
+/// 所有轨道都被租用的时候创建新的轨道，
+/// 所以并发写入的数据分布在不同的轨道文件中
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Allocation {
+    #[default]
+    Sequential,
+    RoundRobin,
+    LeastFull,
+    Affinity,
+}
+
+/// 分配状态
+///
+/// `leased` 已经租用的轨道以及租约数量
+/// `cursor` 轮流分配的上个轨道
+/// `affinity` 线程上次使用的轨道，数量超过上限的时候清空
+#[derive(Default)]
+struct State {
+    leased: HashMap<u16, usize>,
+    cursor: u16,
+    affinity: HashMap<ThreadId, u16>,
+}
+
+/// 轨道租约
+///
+/// 租约释放的时候轨道可以重新分配给其他写入流
+pub struct Lease {
+    state: Arc<Mutex<State>>,
+    id: u16,
+}
+
+/// 轨道分配器
+///
+/// 根据分配策略为写入流选择轨道
+#[derive(Clone)]
+pub struct Allocator {
+    options: Arc<KernelOptions>,
+    state: Arc<Mutex<State>>,
+    tracks: Tracks,
+}
+
+impl Lease {
+    /// 轨道ID
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if let Ok(mut state) = self.state.lock() {
+            if let Some(count) = state.leased.get_mut(&self.id) {
+                *count -= 1;
+                if *count == 0 {
+                    state.leased.remove(&self.id);
+                }
+            }
+        }
+    }
+}
+
+impl Allocator {
+    /// 创建分配器
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let allocator = Allocator::new(Tracks::default(), options);
+    /// ```
+    pub fn new(tracks: Tracks, options: Arc<KernelOptions>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(State::default())),
+            options,
+            tracks,
+        }
+    }
+
+    /// 租用轨道
+    ///
+    /// 从没有租用并且还有空间的轨道中选择一个，
+    /// 没有可以选择的轨道的时候创建新的轨道，
+    /// 轨道ID用完的时候才会和其他写入流共用轨道
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let allocator = Allocator::new(Tracks::default(), options);
+    /// let lease = allocator.acquire().unwrap();
+    /// println!("{}", lease.id());
+    /// ```
+    #[rustfmt::skip]
+    pub fn acquire(&self) -> Result<Lease> {
+        let mut state = lock(&self.state)?;
+        let ids = self.tracks.ids()?;
+
+        // 收集没有租用并且还有空间的轨道，
+        // 以及轨道的剩余分片数量，
+        // 剩余分片数量不需要获取轨道锁
+        let mut candidates = Vec::new();
+        let mut shared = None;
+        for id in ids.iter().copied() {
+            let available = self.tracks.available(id)?;
+            if available == 0 {
+                continue;
+            }
+
+            if state.leased.contains_key(&id) {
+                shared = shared.or(Some(id));
+            } else {
+                candidates.push((id, available));
+            }
+        }
+
+        // 没有可以选择的轨道的时候创建新的轨道，
+        // 轨道ID用完的时候共用已经租用的轨道
+        let id = match self.select(&state, &candidates) {
+            Some(id) => id,
+            None => match ids.last().copied().unwrap_or(0).checked_add(1) {
+                Some(id) => {
+                    self.tracks.create(id, &self.options)?;
+                    id
+                },
+                None => shared.ok_or_else(|| anyhow!("no space left"))?
+            }
+        };
+
+        *state.leased.entry(id).or_insert(0) += 1;
+        state.cursor = id;
+        if self.options.allocation == Allocation::Affinity {
+            let thread = thread::current().id();
+            if state.affinity.len() >= AFFINITY_LIMIT && !state.affinity.contains_key(&thread) {
+                state.affinity.clear();
+            }
+
+            state.affinity.insert(thread, id);
+        }
+
+        Ok(Lease {
+            state: self.state.clone(),
+            id,
+        })
+    }
+
+    /// 根据分配策略选择轨道
+    ///
+    /// 候选轨道按照ID从小到大排序
+    #[rustfmt::skip]
+    fn select(&self, state: &State, candidates: &[(u16, u64)]) -> Option<u16> {
+        let first = candidates.first().map(|(id, _)| *id);
+        match self.options.allocation {
+            Allocation::Sequential => first,
+            Allocation::RoundRobin => candidates
+                .iter()
+                .find(|(id, _)| *id > state.cursor)
+                .map(|(id, _)| *id)
+                .or(first),
+            Allocation::LeastFull => candidates
+                .iter()
+                .max_by_key(|(id, available)| (*available, std::cmp::Reverse(*id)))
+                .map(|(id, _)| *id),
+            Allocation::Affinity => state.affinity
+                .get(&thread::current().id())
+                .filter(|id| candidates.iter().any(|(x, _)| x == *id))
+                .copied()
+                .or(first),
+        }
+    }
+}
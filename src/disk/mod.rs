@@ -1,32 +1,62 @@
 
+pub mod allocator;
+pub mod object;
 pub mod reader;
 pub mod writer;
 
 use super::fs::readdir;
+use super::superblock::Superblock;
+use super::cache::Cache;
+use super::hasher::Hasher;
+use super::lock;
 use std::io::{Read, Write};
-use writer::{Writer, Callback};
+#[cfg(target_os = "linux")]
+use std::os::unix::io::RawFd;
+#[cfg(target_os = "linux")]
+use super::splice;
+use writer::{Writer, Written, Previous, Callback, Intent};
 use reader::Reader;
-use anyhow::Result;
+use allocator::Allocator;
+use object::Object;
+use bytes::BytesMut;
+use anyhow::{anyhow, Result};
 use std::{
-    collections::HashMap,
-    cell::RefCell, 
-    rc::Rc
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex, RwLock},
+    sync::atomic::{AtomicU64, Ordering}
 };
 
 pub use super::{
+    chunk::ChunkFormat,
     index::AllocMap,
     track::Track,
     KernelOptions
 };
 
+/// 轨道项
+///
+/// 轨道以及轨道剩余可以分配的分片数量
+type Entry = (Arc<Mutex<Track>>, Arc<AtomicU64>);
+
 /// 轨道列表
-pub type Tracks = Rc<RefCell<HashMap<u16, Track>>>;
+///
+/// 每个轨道拥有独立的锁，
+/// 不同轨道的读写可以同时进行，
+/// 所有轨道共用同一个分片缓存，
+/// 轨道剩余分片数量保存在轨道锁之外
+#[derive(Clone, Default)]
+pub struct Tracks {
+    tracks: Arc<RwLock<HashMap<u16, Entry>>>,
+    cache: Option<Arc<Cache>>,
+}
 
 /// 内部存储
 ///
 /// 管理所有轨道的读取和写入
 pub struct Disk {
-    options: Rc<KernelOptions>,
+    options: Arc<KernelOptions>,
+    superblock: Superblock,
+    allocator: Allocator,
     tracks: Tracks,
 }
 
@@ -37,34 +67,45 @@ impl Disk {
     ///
     /// ```no_run
     /// use super::{Disk, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
     /// let disk = Disk::new(options);
     /// ```
-    pub fn new(options: Rc<KernelOptions>) -> Self {
+    pub fn new(options: Arc<KernelOptions>) -> Self {
+        let tracks = Tracks::new(match options.chunk_cache_size {
+            0 => None,
+            size => Some(Arc::new(Cache::new((size / options.chunk_size) as usize)))
+        });
+
         Self {
-            tracks: Rc::new(RefCell::new(HashMap::new())),
+            allocator: Allocator::new(tracks.clone(), options.clone()),
+            superblock: Superblock::new(&options),
             options,
+            tracks,
         }
     }
 
     /// 初始化
     ///
     /// 必须对该实例调用初始化，
-    /// 才能进行其他操作
+    /// 才能进行其他操作，
+    /// 首次初始化的时候写入超级块，
+    /// 之后检查配置是否和超级块一致，
+    /// 旧版本目录写入超级块之前检查已有轨道是否符合配置，
+    /// 只读实例不写入超级块也不创建轨道
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::{Disk, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -76,6 +117,13 @@ impl Disk {
     pub fn init(&mut self) -> Result<()> {
         let mut track_count: i32 = 0;
 
+        // 已有超级块的时候检查配置
+        let superblock = Superblock::load(&self.options.path)?;
+        if let Some(superblock) = superblock.as_ref() {
+            superblock.check(&self.options)?;
+            self.superblock = superblock.clone();
+        }
+
         // 读取目录的所有轨道文件，
         // 将找到的轨道索引创建为轨道类，
         // 并推入内部轨道列表
@@ -89,11 +137,23 @@ impl Disk {
                 }
             }
         }
-        
+
+        // 没有超级块的时候说明是新目录或者旧版本目录，
+        // 旧版本目录的格式参数只能从轨道推断，
+        // 所有轨道都符合当前配置之后才使用当前配置写入超级块
+        if superblock.is_none() {
+            for id in self.tracks.ids()? {
+                self.tracks.with(id, |track| track.check_layout())?;
+            }
+
+            if !self.options.read_only {
+                self.superblock.save(&self.options.path)?;
+            }
+        }
 
         // 如果未找到轨道
         // 则创建初始轨道
-        if track_count == 0 {
+        if track_count == 0 && !self.options.read_only {
             self.create_track(1)?;
         }
 
@@ -106,11 +166,10 @@ impl Disk {
     ///
     /// ```no_run
     /// use super::{Disk, KernelOptions};
-    /// use std::collections::HashMap;
     /// use std::fs::File;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -118,11 +177,11 @@ impl Disk {
     /// let mut disk = Disk::new(options);
     /// disk.init().unwrap();
     ///
-    /// let mut file = File::open("test.mp4");
-    /// disk.read(file, HashMap::new()).unwrap();
+    /// let file = File::create("test.mp4").unwrap();
+    /// disk.read(file, vec![(1, vec![8192, 12288])]).unwrap();
     /// ```
     #[rustfmt::skip]
-    pub fn read(&mut self, mut stream: impl Write, alloc_map: AllocMap) -> Result<()> {
+    pub fn read(&self, mut stream: impl Write, alloc_map: AllocMap) -> Result<()> {
         let mut reader = Reader::new(self.tracks.clone(), alloc_map);
 
         // 无限循环
@@ -130,7 +189,7 @@ impl Disk {
         // 写入外部流中
     loop {
         match reader.read()? {
-            Some(data) => stream.write_all(&data)?,
+            Some(data) => stream.write_all(data)?,
             None => break
         }
     }
@@ -142,6 +201,296 @@ impl Disk {
         Ok(())
     }
 
+    /// 读取指定范围
+    ///
+    /// 除尾部分片之外每个分片都是满载的，
+    /// 所以可以直接根据偏移计算出起始分片，
+    /// 跳过范围之前的所有分片
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::fs::File;
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// let file = File::create("test.mp4").unwrap();
+    /// disk.read_range(file, vec![(1, vec![8192, 12288])], 1024, 4096).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn read_range(
+        &self,
+        mut stream: impl Write,
+        alloc_map: AllocMap,
+        offset: u64,
+        len: u64
+    ) -> Result<()> {
+        let diff_size = self.options.diff_size();
+        let mut reader = Reader::new(self.tracks.clone(), alloc_map);
+        reader.seek((offset / diff_size) as usize);
+
+        // 首个分片需要跳过的长度
+        // 以及剩余需要写入的长度
+        let mut skip = (offset % diff_size) as usize;
+        let mut remaining = len;
+
+        // 读取分片直到满足长度
+        // 或者已经没有分片可以读取
+    while remaining > 0 {
+        let data = match reader.read()? {
+            Some(data) => data,
+            None => break
+        };
+
+        // 分片可能比跳过长度短，
+        // 这里说明范围已经超出数据尾部
+        if skip >= data.len() {
+            break;
+        }
+
+        let size = std::cmp::min(
+            (data.len() - skip) as u64,
+            remaining
+        ) as usize;
+
+        stream.write_all(&data[skip..skip + size])?;
+        remaining -= size as u64;
+        skip = 0;
+    }
+
+        stream.flush()?;
+        Ok(())
+    }
+
+    /// 零拷贝发送指定范围
+    ///
+    /// 分片数据位于轨道文件中固定的位置，
+    /// 所以可以直接将每个分片的数据范围
+    /// 从轨道文件发送到套接字或者管道，
+    /// 只有尾部分片需要读取以获得数据长度，
+    /// 带校验的分片格式在发送之前读取并校验每个分片，
+    /// 调用者需要在发送期间阻止分片被释放，
+    /// 只支持Linux
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::net::TcpStream;
+    /// use std::os::unix::io::AsRawFd;
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+    /// disk.splice_range(stream.as_raw_fd(), vec![(1, vec![8192])], 0, u64::MAX).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    #[rustfmt::skip]
+    pub fn splice_range(
+        &self,
+        fd: RawFd,
+        alloc_map: AllocMap,
+        offset: u64,
+        len: u64
+    ) -> Result<()> {
+        let diff_size = self.options.diff_size();
+        let tail = match self.tail(&alloc_map)? {
+            Some((_, _, data)) => data.len() as u64,
+            None => return Ok(())
+        };
+
+        // 除尾部分片之外每个分片都是满载的，
+        // 根据尾部分片长度计算数据总长度
+        let count: u64 = alloc_map.iter().map(|(_, list)| list.len() as u64).sum();
+        let size = (count - 1) * diff_size + tail;
+        let end = std::cmp::min(offset.saturating_add(len), size);
+        let mut position = 0;
+
+        // 跳过范围之前的轨道，
+        // 持有轨道锁的时候只复制轨道文件句柄，
+        // 发送的时候不持有轨道锁
+        let verify = self.options.chunk_format == ChunkFormat::Crc32c;
+        let header_size = self.options.chunk_format.header_size();
+        for (track_id, list) in alloc_map.iter() {
+            let track_end = position + list.len() as u64 * diff_size;
+            if track_end <= offset {
+                position = track_end;
+                continue;
+            }
+
+            if position >= end {
+                break;
+            }
+
+            let file = self.tracks.with(*track_id, |track| track.try_clone())?;
+            for index in list {
+                let start = std::cmp::max(position, offset);
+                let stop = std::cmp::min(position + diff_size, end);
+                if start < stop {
+                    if verify {
+                        self.tracks.with(*track_id, |track| track.read(*index).map(|_| ()))?;
+                    }
+
+                    let skip = index + header_size + start - position;
+                    splice::transfer(&file, skip, (stop - start) as usize, fd)?;
+                }
+
+                position += diff_size;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 获取超级块
+    pub fn superblock(&self) -> &Superblock {
+        &self.superblock
+    }
+
+    /// 获取轨道列表
+    pub fn tracks(&self) -> Tracks {
+        self.tracks.clone()
+    }
+
+    /// 同步所有轨道
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    /// disk.sync().unwrap();
+    /// ```
+    pub fn sync(&self) -> Result<()> {
+        for id in self.tracks.ids()? {
+            self.tracks.with(id, |track| track.sync())?;
+        }
+
+        Ok(())
+    }
+
+    /// 同步分配表涉及的轨道
+    ///
+    /// 每个轨道只同步一次
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    /// disk.sync_tracks(&vec![(1, vec![8192])]).unwrap();
+    /// ```
+    pub fn sync_tracks(&self, alloc_map: &AllocMap) -> Result<()> {
+        let ids: HashSet<u16> = alloc_map.iter().map(|(id, _)| *id).collect();
+        for id in ids {
+            self.tracks.with(id, |track| track.sync())?;
+        }
+
+        Ok(())
+    }
+
+    /// 遍历分片
+    ///
+    /// 按照分配表顺序读取每个分片，
+    /// 将分片所在轨道，位置，下个分片位置以及数据交给外部处理，
+    /// 分片校验失败或者轨道不存在的时候返回错误
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// disk.walk(&vec![(1, vec![24])], |track, offset, next, data| {
+    ///     println!("{} {} {:?} {}", track, offset, next, data.len());
+    /// }).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn walk(
+        &self, 
+        alloc_map: &AllocMap, 
+        mut handle: impl FnMut(u16, u64, Option<u64>, &[u8])
+    ) -> Result<()> {
+        for (track_id, list) in alloc_map {
+            self.tracks.with(*track_id, |track| {
+                for offset in list {
+                    let (next, data) = track.read(*offset)?;
+                    handle(*track_id, *offset, next, data);
+                }
+
+                Ok(())
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// 打开对象句柄
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::io::Read;
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// let mut object = disk.open(vec![(1, vec![8192, 12288])]).unwrap();
+    /// let mut buf = Vec::new();
+    /// object.read_to_end(&mut buf).unwrap();
+    /// ```
+    pub fn open(&self, alloc_map: AllocMap) -> Result<Object> {
+        let count = alloc_map.iter().map(|(_, x)| x.len()).sum();
+        let reader = Reader::new(self.tracks.clone(), alloc_map);
+        Object::new(reader, count, self.options.diff_size())
+    }
+
     /// 打开写入流
     ///
     /// # Examples
@@ -149,9 +498,9 @@ impl Disk {
     /// ```no_run
     /// use super::{Disk, KernelOptions};
     /// use std::fs::File;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -160,11 +509,256 @@ impl Disk {
     /// disk.init().unwrap();
     ///
     /// let mut file = File::open("test.mp4");
-    /// let alloc_map = disk.write(file).unwrap();
+    /// let written = disk.write(file, &|_| Ok(())).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn write(&self, stream: impl Read, intent: Intent) -> Result<Written> {
+        let mut writer = Writer::new(
+            self.tracks.clone(), 
+            self.allocator.clone(), 
+            self.options.clone(),
+            intent
+        );
+        
+        // 写入失败的时候释放已经分配的分片，
+        // 避免分片泄露
+        if let Err(e) = self.write_stream(&mut writer, stream) {
+            if writer.abort().is_ok() {
+                let _ = self.remove(&writer.alloc_map);
+            }
+            
+            return Err(e);
+        }
+
+        Ok(writer.finish())
+    }
+
+    /// 删除数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    /// 
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// disk.remove(0, 16).unwrap();
     /// ```
     #[rustfmt::skip]
-    pub fn write(&mut self, mut stream: impl Read) -> Result<AllocMap> {
-        let mut writer = Writer::new(self.tracks.clone(), self.options.clone());
+    pub fn remove(&self, alloc_map: &AllocMap) -> Result<()> {
+        for (track_id, list) in alloc_map {
+            if self.tracks.contains(*track_id)? {
+                self.tracks.with(*track_id, |track| track.remove(list))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 确保分片已经释放
+    ///
+    /// 用于恢复未完成的操作，
+    /// 跳过已经在失效链表中的分片以及轨道文件中不存在的分片，
+    /// 剩余的分片加入失效链表，所以可以重复调用，
+    /// 失效链表损坏的轨道直接跳过，
+    /// 这些分片留给修复模式的检查回收
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// disk.release(&vec![(1, vec![8192])]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn release(&self, alloc_map: &AllocMap) -> Result<()> {
+        let mut tracks: HashMap<u16, Vec<u64>> = HashMap::new();
+        for (track_id, list) in alloc_map {
+            tracks.entry(*track_id).or_default().extend(list);
+        }
+
+        for (track_id, list) in tracks {
+            if !self.tracks.contains(track_id)? {
+                continue;
+            }
+
+            self.tracks.with(track_id, |track| {
+                let (free, error) = track.free_list()?;
+                if error.is_some() {
+                    return Ok(());
+                }
+
+                let free: HashSet<u64> = free.into_iter().collect();
+                let slots: HashSet<u64> = track.slots().collect();
+                let mut release: Vec<u64> = list
+                    .into_iter()
+                    .filter(|x| slots.contains(x) && !free.contains(x))
+                    .collect();
+                release.sort_unstable();
+                release.dedup();
+                track.release(&release)
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// 追加写入
+    ///
+    /// 从分配表的尾部分片开始继续写入，
+    /// 新分配的分片在写入之前交给`intent`记录，
+    /// 已有的数据不会被重写，
+    /// 返回的写入长度只包含追加的部分，
+    /// 摘要从`hasher`的状态继续计算，
+    /// `hasher`需要已经处理过已有的数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions, Hasher};
+    /// use std::fs::File;
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// let written = disk.write(File::open("a.log").unwrap(), &|_| Ok(())).unwrap();
+    /// let mut hasher = Hasher::default();
+    /// disk.read(&mut hasher, written.alloc_map.clone()).unwrap();
+    ///
+    /// let stream = File::open("b.log").unwrap();
+    /// let written = disk.append(written.alloc_map, stream, &|_| Ok(()), hasher).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn append(
+        &self, 
+        alloc_map: AllocMap, 
+        stream: impl Read, 
+        intent: Intent, 
+        hasher: Hasher
+    ) -> Result<Written> {
+        let (track_id, index) = match alloc_map.last() {
+            Some((id, list)) => (*id, *list.last().unwrap()),
+            None => return self.write(stream, intent)
+        };
+
+        // 读取尾部分片
+        // 作为写入流的上个节点
+        let data = self.tracks.with(track_id, |track| {
+            Ok(BytesMut::from(track.read(index)?.1))
+        })?;
+
+        let count = alloc_map.iter().map(|(_, x)| x.len()).sum();
+        let tail = Previous::new(track_id, index, data.clone());
+        let mut writer = Writer::resume(
+            self.tracks.clone(), 
+            self.allocator.clone(), 
+            self.options.clone(), 
+            intent,
+            alloc_map, 
+            tail,
+            hasher
+        );
+
+        // 追加失败的时候还原尾部分片，
+        // 并释放新分配的分片，
+        // 这样已有数据不受影响
+        if let Err(e) = self.write_stream(&mut writer, stream) {
+            if writer.abort().is_ok() {
+                let _ = self.restore(track_id, index, &data);
+                let _ = self.remove(&split_alloc_map(&writer.alloc_map, count));
+            }
+
+            return Err(e);
+        }
+
+        Ok(writer.finish())
+    }
+
+    /// 读取尾部分片
+    ///
+    /// 返回分配表最后一个分片的轨道，位置以及数据，
+    /// 分配表为空的时候返回空
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// let tail = disk.tail(&vec![(1, vec![24])]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn tail(&self, alloc_map: &AllocMap) -> Result<Option<(u16, u64, Vec<u8>)>> {
+        let (track_id, index) = match alloc_map.last() {
+            Some((id, list)) => (*id, *list.last().unwrap()),
+            None => return Ok(None)
+        };
+
+        let data = self.tracks.with(track_id, |track| Ok(track.read(index)?.1.to_vec()))?;
+        Ok(Some((track_id, index, data)))
+    }
+
+    /// 还原尾部分片
+    ///
+    /// 将分片重写为没有下个分片的尾部分片，
+    /// 用于回滚没有完成的追加写入
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Disk, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut disk = Disk::new(options);
+    /// disk.init().unwrap();
+    ///
+    /// disk.restore(1, 24, b"hello").unwrap();
+    /// ```
+    pub fn restore(&self, track_id: u16, index: u64, data: &[u8]) -> Result<()> {
+        self.tracks.with(track_id, |track| track.write(None, data, index))
+    }
+
+    /// 将外部流写入轨道
+    ///
+    /// 读取外部流直到结束，
+    /// 处理写入流返回的回调任务
+    #[rustfmt::skip]
+    fn write_stream(&self, writer: &mut Writer, mut stream: impl Read) -> Result<()> {
         let mut buffer = [0; 4096];
         let mut size = 1;
 
@@ -187,59 +781,159 @@ impl Disk {
         };
         
         // 向轨道写入数据
-        // 处理写入返回，如创建新轨道，
-        // 如果轨道返回头部索引，说明写入完成
-        if let Some(callback) = writer.write(data)? {
-            match callback {
-                Callback::CreateTrack(track) => self.create_track(track)?,
-                Callback::Done => return Ok(writer.alloc_map),
-                _ => ()
-            }
+        // 写入流返回完成的时候跳出
+        if let Some(Callback::Done) = writer.write(data)? {
+            return Ok(());
         }
     }
     }
 
-    /// 删除数据
+    /// 创建轨道
+    ///
+    /// 创建轨道类并初始化，
+    /// 将轨道添加到内部的轨道列表
+    fn create_track(&self, id: u16) -> Result<()> {
+        self.tracks.create(id, &self.options)
+    }
+}
+
+impl Tracks {
+    /// 创建轨道列表
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use super::{Disk, KernelOptions};
-    /// use std::rc::Rc;
-    /// 
-    /// let options = Rc::new(KernelOptions::from(
-    ///     Path::new("./.static"), 
-    ///     1024 * 1024 * 1024 * 1
-    /// ));
+    /// use super::{Tracks, Cache};
+    /// use std::sync::Arc;
     ///
-    /// let mut disk = Disk::new(options);
-    /// disk.init().unwrap();
+    /// let tracks = Tracks::new(Some(Arc::new(Cache::new(1024))));
+    /// ```
+    pub fn new(cache: Option<Arc<Cache>>) -> Self {
+        Self {
+            tracks: Arc::default(),
+            cache,
+        }
+    }
+
+    /// 轨道是否存在
+    pub fn contains(&self, id: u16) -> Result<bool> {
+        Ok(self.tracks
+            .read()
+            .map_err(|_| anyhow!("tracks poisoned"))?
+            .contains_key(&id))
+    }
+
+    /// 所有轨道ID
     ///
-    /// disk.remove(0, 16).unwrap();
+    /// 按照从小到大排序
+    pub fn ids(&self) -> Result<Vec<u16>> {
+        let mut ids: Vec<u16> = self.tracks
+            .read()
+            .map_err(|_| anyhow!("tracks poisoned"))?
+            .keys()
+            .copied()
+            .collect();
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// 获取轨道
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Tracks;
+    ///
+    /// let tracks = Tracks::default();
+    /// let track = tracks.get(1).unwrap();
+    /// let size = track.lock().unwrap().size();
     /// ```
     #[rustfmt::skip]
-    pub fn remove(&mut self, alloc_map: &AllocMap) -> Result<()> {
-        let mut tracks = self.tracks.borrow_mut();
-        for (track_id, list) in alloc_map {
-            if let Some(track) = tracks.get_mut(track_id) {
-                track.remove(list)?;
-            }
+    pub fn get(&self, id: u16) -> Result<Arc<Mutex<Track>>> {
+        match self.tracks.read().map_err(|_| anyhow!("tracks poisoned"))?.get(&id) {
+            None => Err(anyhow!("track not found: {}", id)),
+            Some((x, _)) => Ok(x.clone())
         }
+    }
 
-        Ok(())
+    /// 轨道剩余可以分配的分片数量
+    ///
+    /// 不需要获取轨道锁，
+    /// 返回的是轨道状态最后一次变化时的值
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Tracks;
+    ///
+    /// let tracks = Tracks::default();
+    /// let available = tracks.available(1).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn available(&self, id: u16) -> Result<u64> {
+        match self.tracks.read().map_err(|_| anyhow!("tracks poisoned"))?.get(&id) {
+            None => Err(anyhow!("track not found: {}", id)),
+            Some((_, x)) => Ok(x.load(Ordering::Relaxed))
+        }
+    }
+
+    /// 操作轨道
+    ///
+    /// 持有轨道锁的时候调用处理函数，
+    /// 处理函数返回之后释放轨道锁
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Tracks;
+    ///
+    /// let tracks = Tracks::default();
+    /// let data = tracks.with(1, |track| {
+    ///     Ok(track.read(8192)?.1.to_vec())
+    /// }).unwrap();
+    /// ```
+    pub fn with<T>(&self, id: u16, handle: impl FnOnce(&mut Track) -> Result<T>) -> Result<T> {
+        let track = self.get(id)?;
+        let mut track = lock(&track)?;
+        handle(&mut track)
     }
 
     /// 创建轨道
     ///
-    /// 创建轨道类并初始化，
-    /// 将轨道添加到内部的轨道列表
+    /// 创建过程持有轨道列表的写锁，
+    /// 轨道已经存在的时候不做任何操作，
+    /// 避免重复初始化覆盖轨道文件头
     #[rustfmt::skip]
-    fn create_track(&mut self, id: u16) -> Result<()> {
-        let mut track = Track::new(id, self.options.clone())?;
+    pub fn create(&self, id: u16, options: &Arc<KernelOptions>) -> Result<()> {
+        let mut tracks = self.tracks.write().map_err(|_| anyhow!("tracks poisoned"))?;
+        if tracks.contains_key(&id) {
+            return Ok(());
+        }
+
+        let mut track = Track::new(id, options.clone(), self.cache.clone())?;
         track.init()?;
-        self.tracks
-            .borrow_mut()
-            .insert(id, track);
+        let remaining = track.remaining();
+        tracks.insert(id, (Arc::new(Mutex::new(track)), remaining));
         Ok(())
     }
 }
+
+/// 拆分分配表
+///
+/// 跳过前`skip`个分片，
+/// 返回剩余分片组成的分配表
+#[rustfmt::skip]
+fn split_alloc_map(alloc_map: &AllocMap, mut skip: usize) -> AllocMap {
+    let mut result = Vec::new();
+    for (id, list) in alloc_map {
+        if skip >= list.len() {
+            skip -= list.len();
+            continue;
+        }
+
+        result.push((*id, list[skip..].to_vec()));
+        skip = 0;
+    }
+
+    result
+}
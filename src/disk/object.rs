@@ -0,0 +1,174 @@
+use super::super::locks::KeyGuard;
+use super::reader::Reader;
+use anyhow::Result;
+use std::convert::TryFrom;
+use std::io::{
+    Error, 
+    ErrorKind, 
+    Read, 
+    Seek, 
+    SeekFrom
+};
+
+/// 对象句柄
+///
+/// 以随机访问的方式读取数据，
+/// 内部缓存当前所在的分片，
+/// 顺序读取的时候不会重复读取分片，
+/// 也不会重新定位读取器
+///
+/// `next` 读取器下一次返回的分片序号  
+/// `_guard` 键的读锁，句柄存活期间分片不会被释放
+pub struct Object {
+    _guard: Option<KeyGuard>,
+    chunk: Option<(usize, Vec<u8>)>,
+    diff_size: u64,
+    reader: Reader,
+    cursor: u64,
+    next: usize,
+    size: u64,
+}
+
+impl Object {
+    /// 创建对象句柄
+    ///
+    /// 除尾部分片之外每个分片都是满载的，
+    /// 所以只需要读取尾部分片就能得到数据长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Object, Reader, Tracks};
+    ///
+    /// let reader = Reader::new(Tracks::default(), vec![(1, vec![8192, 12288])]);
+    /// let object = Object::new(reader, 2, 4086).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn new(mut reader: Reader, count: usize, diff_size: u64) -> Result<Self> {
+        let size = if count > 0 {
+            reader.seek(count - 1);
+            let last = reader.read()?.map(|x| x.len()).unwrap_or(0);
+            (count as u64 - 1) * diff_size + last as u64
+        } else {
+            0
+        };
+
+        Ok(Self {
+            _guard: None,
+            chunk: None,
+            next: count,
+            cursor: 0,
+            diff_size,
+            reader,
+            size,
+        })
+    }
+
+    /// 持有键的读锁
+    ///
+    /// 读锁随句柄一起释放
+    pub(crate) fn hold(mut self, guard: KeyGuard) -> Self {
+        self._guard = Some(guard);
+        self
+    }
+
+    /// 获取数据长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Object, Reader, Tracks};
+    ///
+    /// let reader = Reader::new(Tracks::default(), Vec::new());
+    /// let object = Object::new(reader, 0, 4086).unwrap();
+    /// assert_eq!(object.len(), 0);
+    /// ```
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    /// 数据是否为空
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// 加载分片
+    ///
+    /// 如果分片已经在缓存中则直接返回，
+    /// 否则从轨道中读取分片，
+    /// 分片正好是读取器的下一个分片的时候不重新定位，
+    /// 保留读取器已经预读的分片
+    #[rustfmt::skip]
+    fn load(&mut self, index: usize) -> Result<&[u8]> {
+        let cached = match &self.chunk {
+            Some((i, _)) => *i == index,
+            None => false
+        };
+
+        if !cached {
+            if self.next != index {
+                self.reader.seek(index);
+            }
+
+            // 读取失败的时候读取器的位置未知，
+            // 下次加载需要重新定位
+            self.next = usize::MAX;
+            let data = self.reader.read()?.map(|x| x.to_vec()).unwrap_or_default();
+            self.chunk = Some((index, data));
+            self.next = index + 1;
+        }
+
+        Ok(&self.chunk.as_ref().unwrap().1)
+    }
+}
+
+impl Read for Object {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        if self.cursor >= self.size || buf.is_empty() {
+            return Ok(0);
+        }
+
+        // 计算游标所在分片
+        // 以及分片内部的偏移
+        let index = (self.cursor / self.diff_size) as usize;
+        let skip = (self.cursor % self.diff_size) as usize;
+        let chunk = self.load(index)
+            .map_err(Error::other)?;
+        if skip >= chunk.len() {
+            return Ok(0);
+        }
+
+        let size = std::cmp::min(chunk.len() - skip, buf.len());
+        buf[..size].copy_from_slice(&chunk[skip..skip + size]);
+        self.cursor += size as u64;
+        Ok(size)
+    }
+}
+
+impl Seek for Object {
+    #[rustfmt::skip]
+    fn seek(&mut self, pos: SeekFrom) -> Result<u64, Error> {
+        let cursor = match pos {
+            SeekFrom::Start(x) => x as i128,
+            SeekFrom::End(x) => self.size as i128 + x as i128,
+            SeekFrom::Current(x) => self.cursor as i128 + x as i128,
+        };
+
+        // 不允许移动到数据头部之前，
+        // 但是允许移动到数据尾部之后
+        if cursor < 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput, 
+                "invalid seek to a negative position"
+            ));
+        }
+
+        // 位置超出u64范围的时候返回错误
+        self.cursor = u64::try_from(cursor).map_err(|_| Error::new(
+            ErrorKind::InvalidInput, 
+            "invalid seek to an overflowing position"
+        ))?;
+
+        Ok(self.cursor)
+    }
+}
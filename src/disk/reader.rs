@@ -1,16 +1,27 @@
 use super::{AllocMap, Tracks};
+use std::collections::VecDeque;
 use anyhow::Result;
 
+/// 最大批量读取分片数量
+const READ_AHEAD: usize = 32;
+
 /// 读取流
 ///
 /// 从轨道中读取数据，
-/// 游标由内部维护
+/// 游标由内部维护，
+/// 连续读取的时候批量读取同一个轨道中的后续分片，
+/// 物理位置连续的分片合并为一次读取，
+/// 并且提前通知操作系统预读下一批分片，
+/// 分片内容存放在复用的缓冲区中
 pub struct Reader {
+    ranges: VecDeque<(usize, usize)>,
+    buffer: Vec<u8>,
     alloc_map: AllocMap,
     track_index: usize,
     alloc_size: usize,
     track_id: usize,
     tracks: Tracks,
+    window: usize,
 }
 
 impl Reader {
@@ -19,16 +30,18 @@ impl Reader {
     /// # Examples
     ///
     /// ```no_run
-    /// use super::Reader;
-    /// use std::collections::HashMap;
+    /// use super::{Reader, Tracks};
     ///
-    /// let reader = Reader::new(HashMap::new(), HashMap::new());
+    /// let reader = Reader::new(Tracks::default(), vec![(1, vec![8192, 12288])]);
     /// ```
     pub fn new(tracks: Tracks, alloc_map: AllocMap) -> Self {
         Self {
             alloc_size: alloc_map.len(),
+            ranges: VecDeque::new(),
+            buffer: Vec::new(),
             track_index: 0,
             track_id: 0,
+            window: 1,
             alloc_map,
             tracks,
         }
@@ -36,48 +49,105 @@ impl Reader {
 
     /// 读取数据
     ///
+    /// 已经读取的分片用完的时候从当前轨道批量读取，
+    /// 每次批量读取之后读取数量翻倍，
+    /// 直到达到最大批量读取数量，
+    /// 返回的分片内容在下次读取之前有效
+    ///
     /// # Examples
     ///
     /// ```no_run
-    /// use super::Reader;
-    /// use std::collections::HashMap;
+    /// use super::{Reader, Tracks};
     ///
-    /// let reader = Reader::new(HashMap::new(), HashMap::new());
+    /// let mut reader = Reader::new(Tracks::default(), vec![(1, vec![8192, 12288])]);
+    /// let data = reader.read().unwrap();
+    /// ```
+    pub fn read(&mut self) -> Result<Option<&[u8]>> {
+        if self.ranges.is_empty() {
+            self.fill()?;
+        }
+
+        Ok(self.ranges.pop_front().map(move |(start, end)| &self.buffer[start..end]))
+    }
+
+    /// 移动游标
+    ///
+    /// 将游标移动到分配表中第`index`个分片，
+    /// 分配表已经完整加载在内存中，
+    /// 所以这里不需要遍历轨道文件，
+    /// 移动游标会丢弃已经读取的分片
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Reader, Tracks};
+    ///
+    /// let mut reader = Reader::new(Tracks::default(), vec![(1, vec![8192, 12288])]);
+    /// reader.seek(1);
     /// let data = reader.read().unwrap();
     /// ```
     #[rustfmt::skip]
-    pub fn read(&mut self) -> Result<Option<Vec<u8>>> {
+    pub fn seek(&mut self, mut index: usize) {
+        self.ranges.clear();
+        self.track_index = 0;
+        self.track_id = 0;
+        self.window = 1;
         
-        // 如果轨道遍历完成
-        // 则返回`None`表示读取为空
+        // 逐个轨道跳过
+        // 直到找到分片所在的轨道
+        for (_, list) in self.alloc_map.iter() {
+            if index < list.len() {
+                self.track_index = index;
+                return;
+            }
+
+            index -= list.len();
+            self.track_id += 1;
+        }
+    }
+
+    /// 批量读取
+    ///
+    /// 读取当前轨道中的后续分片，
+    /// 读取完成之后通知轨道预读下一批分片，
+    /// 轨道遍历完成的时候不做处理
+    #[rustfmt::skip]
+    fn fill(&mut self) -> Result<()> {
+        self.buffer.clear();
         if self.track_id >= self.alloc_size {
-            return Ok(None);
+            return Ok(());
         }
 
-        // 获取轨道分配表索引
-        // 获取分片数据内容
-        let (track_id, list) = self.alloc_map.get(self.track_id).unwrap();
-        let mut tracks = self.tracks.borrow_mut();
-        let track = tracks.get_mut(&track_id).unwrap();
-        let index = list.get(self.track_index).unwrap();
-        let (next, chunk) = track.read(*index)?;
-        
-        // 如果没有后续分片
-        // 则返回`None`表示读取为空
-        if let None = next {
-            return Ok(None);
+        // 获取轨道分配表索引，
+        // 本次读取的分片以及下一批分片
+        let (track_id, list) = &self.alloc_map[self.track_id];
+        let count = list.len();
+        let end = std::cmp::min(self.track_index + self.window, count);
+        let next = std::cmp::min(end + std::cmp::min(self.window * 2, READ_AHEAD), count);
+        let offsets = &list[self.track_index..end];
+        let ahead = &list[end..next];
+        let buffer = &mut self.buffer;
+        let sizes = self.tracks.with(*track_id, |track| {
+            let sizes = track.read_batch(offsets, buffer)?;
+            track.prefetch(ahead)?;
+            Ok(sizes)
+        })?;
+
+        let mut start = 0;
+        for size in sizes {
+            self.ranges.push_back((start, start + size));
+            start += size;
         }
 
         // 检查是否抵达轨道尾部
         // 如果抵达尾部则前进到下个轨道
-        self.track_index += 1;
-        if self.track_index >= list.len() {
+        self.track_index = end;
+        if self.track_index >= count {
             self.track_index = 0;
             self.track_id += 1;
         }
 
-        Ok(Some(
-            chunk.to_vec()
-        ))
+        self.window = std::cmp::min(self.window * 2, READ_AHEAD);
+        Ok(())
     }
 }
@@ -1,20 +1,38 @@
-use std::collections::HashMap;
+use super::super::hasher::Hasher;
+use std::collections::HashSet;
 use bytes::BytesMut;
 use anyhow::Result;
-use std::rc::Rc;
+use std::sync::Arc;
 use super::{
+    allocator::{Allocator, Lease},
     KernelOptions,
     AllocMap,
     Tracks
 };
 
+/// 写入缓冲区长度
+///
+/// 写入队列中的分片总长度达到这个长度的时候写入轨道
+const WRITE_BUFFER: usize = 1024 * 1024;
+
 /// 写入回调任务
 pub enum Callback {
-    Index(u64),
-    CreateTrack(u16),
     Done,
 }
 
+/// 写入结果
+///
+/// `alloc_map` 分配表  
+/// `size` 写入长度  
+/// `digest` 数据摘要(SHA-256)  
+/// `state` 摘要计算状态
+pub struct Written {
+    pub alloc_map: AllocMap,
+    pub size: u64,
+    pub digest: [u8; 32],
+    pub state: [u32; 8],
+}
+
 /// 链表上个节点
 ///
 /// 因为链表的特性导致写入需要延迟，
@@ -25,73 +43,174 @@ pub struct Previous {
     data: BytesMut,
 }
 
+impl Previous {
+    /// 创建节点
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Previous;
+    /// use bytes::BytesMut;
+    ///
+    /// let previous = Previous::new(1, 24, BytesMut::from(&b"hello"[..]));
+    /// ```
+    pub fn new(track: u16, index: u64, data: BytesMut) -> Self {
+        Self {
+            track,
+            index,
+            data,
+        }
+    }
+}
+
+/// 分配记录
+///
+/// 写入新分配的分片之前调用，
+/// 用于在日志中记录分配的分片
+pub type Intent<'a> = &'a dyn Fn(&AllocMap) -> Result<()>;
+
 /// 写入流
 ///
 /// 写入数据到轨道中，
-/// 内部维护游标和写入策略
-pub struct Writer {
+/// 内部维护游标和写入策略，
+/// 写入期间持有当前轨道的租约，
+/// 已经确定下个节点的分片先放入队列批量写入，
+/// 连续分配的分片在写入的时候合并为一次写入
+pub struct Writer<'a> {
     pub alloc_map: AllocMap,
-    index: HashMap<u16, usize>,
+    pub size: u64,
+    affected: HashSet<u16>,
     previous: Option<Previous>,
+    pending: Vec<(u64, Option<u64>, BytesMut)>,
+    pending_track: u16,
+    allocated: AllocMap,
+    intent: Intent<'a>,
+    lease: Option<Lease>,
+    allocator: Allocator,
     buffer: BytesMut,
+    hasher: Hasher,
     diff_size: usize,
+    batch_size: usize,
     tracks: Tracks,
-    track: u16
 }
 
-impl Writer {
+impl<'a> Writer<'a> {
     /// 创建写入流
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use super::{Writer, KernelOptions};
-    /// use std::rc::Rc;
+    /// use super::{Writer, Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut tracks = HashMap::new();
-    /// let writer = Writer::new(&mut tracks, options);
+    /// let tracks = Tracks::default();
+    /// let allocator = Allocator::new(tracks.clone(), options.clone());
+    /// let writer = Writer::new(tracks, allocator, options, &|_| Ok(()));
     /// ```
-    pub fn new(tracks: Tracks, options: Rc<KernelOptions>) -> Self {
+    pub fn new(
+        tracks: Tracks, 
+        allocator: Allocator, 
+        options: Arc<KernelOptions>, 
+        intent: Intent<'a>
+    ) -> Self {
         Self {
-            diff_size: (options.chunk_size - 10) as usize,
+            batch_size: std::cmp::max(WRITE_BUFFER / options.chunk_size as usize, 1),
+            diff_size: options.diff_size() as usize,
             buffer: BytesMut::new(),
             alloc_map: Vec::new(),
-            index: HashMap::new(),
+            hasher: Hasher::default(),
+            affected: HashSet::new(),
+            pending: Vec::new(),
+            pending_track: 0,
+            allocated: Vec::new(),
             previous: None,
-            track: 1,
+            lease: None,
+            size: 0,
+            allocator,
+            intent,
             tracks,
         }
     }
 
+    /// 创建追加写入流
+    ///
+    /// 以已经存在的分配表为起点继续写入，
+    /// 尾部分片作为上个节点，
+    /// 如果尾部分片没有填满将优先填充尾部分片，
+    /// 摘要从`hasher`的状态继续计算
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Writer, Previous, Allocator, KernelOptions, Tracks, Hasher};
+    /// use bytes::BytesMut;
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let tail = Previous::new(1, 24, BytesMut::from(&b"hello"[..]));
+    /// let tracks = Tracks::default();
+    /// let allocator = Allocator::new(tracks.clone(), options.clone());
+    /// let writer = Writer::resume(
+    ///     tracks, 
+    ///     allocator, 
+    ///     options, 
+    ///     &|_| Ok(()), 
+    ///     vec![(1, vec![24])], 
+    ///     tail, 
+    ///     Hasher::default()
+    /// );
+    /// ```
+    pub fn resume(
+        tracks: Tracks, 
+        allocator: Allocator,
+        options: Arc<KernelOptions>, 
+        intent: Intent<'a>,
+        alloc_map: AllocMap, 
+        tail: Previous,
+        hasher: Hasher
+    ) -> Self {
+        let mut writer = Self::new(tracks, allocator, options, intent);
+        writer.affected.insert(tail.track);
+        writer.hasher = hasher;
+        writer.previous = Some(tail);
+        writer.alloc_map = alloc_map;
+        writer
+    }
+
     /// 写入数据
     ///
     /// # Examples
     ///
     /// ```no_run
-    /// use super::{Writer, KernelOptions};
-    /// use std::collections::HashMap;
-    /// use std::rc::Rc;
+    /// use super::{Writer, Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
     /// 
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut tracks = HashMap::new();
-    /// let mut writer = Writer::new(&mut tracks, options, |id| {
-    /// ...
-    /// });
-    ///
-    /// writer.write(Some(&b"hello")).unwrap();
+    /// let tracks = Tracks::default();
+    /// let allocator = Allocator::new(tracks.clone(), options.clone());
+    /// let mut writer = Writer::new(tracks, allocator, options, &|_| Ok(()));
+    /// writer.write(Some(&b"hello"[..])).unwrap();
     /// ```
     pub fn write(&mut self, chunk: Option<&[u8]>) -> Result<Option<Callback>> {
         match chunk {
-            Some(data) => self.write_buffer(data, false),
+            Some(data) => {
+                self.size += data.len() as u64;
+                self.hasher.update(data);
+                self.write_buffer(data, false)
+            },
             None => self.done(),
         }
     }
@@ -107,57 +226,187 @@ impl Writer {
         // 检查是否有未处理的数据
         // 如果存在未处理数据则将数据全部写入
         if self.buffer.len() > 0 {
-            self.write_buffer(&[], true)?;
+            if let Some(callback) = self.write_buffer(&[], true)? {
+                return Ok(Some(callback));
+            }
+        }
+
+        self.close()?;
+        Ok(Some(
+            Callback::Done
+        ))
+    }
+
+    /// 完成写入
+    ///
+    /// 写入流返回`Callback::Done`之后调用，
+    /// 返回分配表，写入长度，数据摘要以及摘要计算状态
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Writer, Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let tracks = Tracks::default();
+    /// let allocator = Allocator::new(tracks.clone(), options.clone());
+    /// let mut writer = Writer::new(tracks, allocator, options, &|_| Ok(()));
+    /// writer.write(None).unwrap();
+    ///
+    /// let written = writer.finish();
+    /// ```
+    pub fn finish(self) -> Written {
+        Written {
+            digest: self.hasher.finalize(),
+            state: self.hasher.state(),
+            alloc_map: self.alloc_map,
+            size: self.size,
         }
+    }
+
+    /// 中止写入
+    ///
+    /// 写入失败的时候将已经分配的分片链接完整，
+    /// 这样分配表可以直接交给轨道释放
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Writer, Allocator, KernelOptions, Tracks};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let tracks = Tracks::default();
+    /// let allocator = Allocator::new(tracks.clone(), options.clone());
+    /// let mut writer = Writer::new(tracks, allocator, options, &|_| Ok(()));
+    /// writer.abort().unwrap();
+    /// ```
+    pub fn abort(&mut self) -> Result<()> {
+        self.buffer.clear();
+        self.close()
+    }
 
+    /// 关闭写入流
+    ///
+    /// 写入未处理的节点，
+    /// 并保存所有受影响轨道的状态
+    #[rustfmt::skip]
+    fn close(&mut self) -> Result<()> {
+        
         // 检查是否有未处理的节点
         // 如果有未处理节点则将节点写入
-        let mut tracks = self.tracks.borrow_mut();
-        if let Some(previous) = self.previous.as_ref() {
-            let track = tracks.get_mut(&previous.track).unwrap();
-            track.write(None, &previous.data, previous.index)?;
+        if let Some(previous) = self.previous.take() {
+            self.push(previous.track, previous.index, None, previous.data)?;
         }
 
+        // 写入队列中剩余的分片
+        self.flush_pending()?;
+
         // 遍历所有受影响的轨道
         // 为每个轨道保存状态
-        for (track_id, _) in self.index.iter() {
-            tracks.get_mut(track_id).unwrap().flush()?;
+        for track_id in self.affected.iter() {
+            self.tracks.with(*track_id, |track| track.flush())?;
         }
 
-        Ok(Some(
-            Callback::Done
-        ))
+        self.lease = None;
+        Ok(())
     }
 
     /// 分配写入轨道
     ///
-    /// 为内部分配合理的轨道游标
+    /// 在租用的轨道上分配分片，
+    /// 轨道已满的时候释放租约并重新租用轨道，
+    /// 返回轨道ID和分片位置
     #[rustfmt::skip]
-    fn alloc(&mut self) -> Result<Callback> {
-        let mut tracks = self.tracks.borrow_mut();
+    fn alloc(&mut self) -> Result<(u16, u64)> {
         
         // 无限循环
         // 直到匹配出可以写入的轨道
     loop {
         
-        // 检查轨道是否存在
-        // 如果轨道不存在通知上级创建轨道
-        if !tracks.contains_key(&self.track) {
-            return Ok(Callback::CreateTrack(self.track))
-        }
+        // 没有租约的时候
+        // 通过分配器租用轨道
+        let track_id = match self.lease.as_ref() {
+            Some(lease) => lease.id(),
+            None => {
+                let lease = self.allocator.acquire()?;
+                let id = lease.id();
+                self.lease = Some(lease);
+                id
+            }
+        };
 
         // 检查轨道大小是否可以写入分片
-        // 如果可以则跳出，否则递加到下个轨道
-        let track = tracks.get_mut(&self.track).unwrap();
-        if let Some(index) = track.alloc()? {
-            return Ok(Callback::Index(index));
+        // 如果可以则跳出，否则释放租约重新分配
+        if let Some(index) = self.tracks.with(track_id, |track| track.alloc())? {
+            return Ok((track_id, index));
         } else {
-            self.track += 1;
+            self.lease = None;
             continue;
         }
     }
     }
 
+    /// 放入写入队列
+    ///
+    /// 队列中的分片属于同一个轨道，
+    /// 轨道变化或者队列已满的时候先写入队列，
+    /// 分片按照分配顺序排列，
+    /// 从轨道尾部连续分配的分片在轨道中也是连续的
+    fn push(&mut self, track: u16, index: u64, next: Option<u64>, data: BytesMut) -> Result<()> {
+        if !self.pending.is_empty() && self.pending_track != track {
+            self.flush_pending()?;
+        }
+
+        self.pending_track = track;
+        self.pending.push((index, next, data));
+        if self.pending.len() >= self.batch_size {
+            self.flush_pending()?;
+        }
+
+        Ok(())
+    }
+
+    /// 写入队列
+    ///
+    /// 写入之前先记录还没有记录的分配，
+    /// 并保存轨道状态，
+    /// 这样分片被覆盖的时候已经不在磁盘上的失效链表中，
+    /// 写入失败的时候保留队列，
+    /// 中止写入的时候会再次尝试
+    #[rustfmt::skip]
+    fn flush_pending(&mut self) -> Result<()> {
+        if !self.allocated.is_empty() {
+            (self.intent)(&self.allocated)?;
+            self.allocated.clear();
+        }
+
+        if self.pending.is_empty() {
+            return Ok(());
+        }
+
+        let chunks: Vec<(u64, Option<u64>, &[u8])> = self.pending
+            .iter()
+            .map(|(index, next, data)| (*index, *next, &data[..]))
+            .collect();
+        self.tracks.with(self.pending_track, |track| {
+            track.flush()?;
+            track.write_batch(&chunks)
+        })?;
+
+        self.pending.clear();
+        Ok(())
+    }
+
     /// 将数据写入轨道
     ///
     /// 将数据自动分配到有空间写入的轨道上
@@ -176,34 +425,30 @@ impl Writer {
             break;
         }
 
+        // 追加写入的时候尾部分片可能没有填满，
+        // 这时候先将缓冲区数据填充到尾部分片
+        if let Some(previous) = self.previous.as_mut() {
+            if previous.data.len() < diff_size {
+                let size = std::cmp::min(diff_size - previous.data.len(), buffer_size);
+                previous.data.extend_from_slice(&self.buffer.split_to(size));
+                continue;
+            }
+        }
+
         // 检查缓冲区大小是否满足最小写入大小
         // 这里有一种情况就是完全清空，如果完全清空的时候则不检查
         if !free && buffer_size < diff_size  {
             break;
         }
 
-        // 尝试分配轨道
-        let index;
-        let alloc_result = self.alloc()?;
-        if let Callback::Index(offset) = alloc_result {
-            index = offset;
-        } else {
-            return Ok(Some(alloc_result))
-        }
-
-        // 为了避免不必要的重复写入
-        // 所以这里先检查轨道索引是否存在
-        if !self.index.contains_key(&self.track) {
-            self.index.insert(self.track, self.alloc_map.len());
-            self.alloc_map.push((self.track, Vec::new()));
-        }
+        // 分配轨道
+        let (track_id, index) = self.alloc()?;
+        self.affected.insert(track_id);
 
         // 如果存在节点缓存
-        // 则将节点缓存写入到轨道中
-        if let Some(previous) = self.previous.as_ref() {
-            let mut tracks = self.tracks.borrow_mut();
-            let track = tracks.get_mut(&previous.track).unwrap();
-            track.write(Some(index), &previous.data, previous.index)?;
+        // 则将节点缓存放入写入队列
+        if let Some(previous) = self.previous.take() {
+            self.push(previous.track, previous.index, Some(index), previous.data)?;
         }
 
         // 如果缓冲区大小比分配长度小
@@ -217,14 +462,17 @@ impl Writer {
         // 重置节点缓存
         self.previous = Some(Previous {
             data: self.buffer.split_to(off_index),
-            track: self.track,
+            track: track_id,
             index
         });
 
-        // 将节点索引写入分配表
-        if let Some(offset) = self.index.get(&self.track) {
-            if let Some((_, list)) = self.alloc_map.get_mut(*offset) {
-                list.push(index);
+        // 将节点索引写入分配表，
+        // 分配表按照写入顺序排列，
+        // 所以轨道变化的时候总是新建一段
+        for alloc_map in [&mut self.alloc_map, &mut self.allocated] {
+            match alloc_map.last_mut() {
+                Some((id, list)) if *id == track_id => list.push(index),
+                _ => alloc_map.push((track_id, vec![index]))
             }
         }
     }
@@ -0,0 +1,107 @@
+use std::time::{Duration, Instant};
+
+/// 持久化策略
+///
+/// `None` 不主动同步，交给操作系统  
+/// `OnClose` 关闭实例的时候同步  
+/// `PerObject` 每次写入或者删除数据之后同步  
+/// `Bytes` 每写入指定长度的数据同步一次  
+/// `Interval` 写入的时候距离上次同步超过指定时间则同步
+///
+/// 同步会对轨道文件调用`fdatasync`，
+/// 并且对索引使用同步写入，
+/// 轨道文件总是先于索引同步.
+///
+/// 只有`PerObject`保证索引不会指向没有落盘的数据，
+/// 这时候日志也会同步写入，
+/// 其他策略下两次同步之间的索引写入没有同步，
+/// 系统崩溃之后索引可能已经落盘而分片数据没有落盘，
+/// 最近一次同步之后写入的数据可能丢失或者损坏，
+/// 使用`Crc32c`分片格式的时候可以通过巡检发现损坏的数据.
+///
+/// `Interval`只在写入的时候检查时间，
+/// 没有定时器，存储空闲的时候不会同步，
+/// 最后一次写入的数据在下一次写入或者关闭实例的时候才同步
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Durability {
+    #[default]
+    None,
+    OnClose,
+    PerObject,
+    Bytes(u64),
+    Interval(Duration),
+}
+
+/// 同步计数
+///
+/// 记录上次同步之后写入的长度和时间，
+/// 根据持久化策略判断本次操作是否需要同步
+pub struct Syncer {
+    durability: Durability,
+    written: u64,
+    last: Instant,
+}
+
+impl Syncer {
+    /// 创建实例
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Syncer, Durability};
+    ///
+    /// let syncer = Syncer::new(Durability::Bytes(1024 * 1024));
+    /// ```
+    pub fn new(durability: Durability) -> Self {
+        Self {
+            last: Instant::now(),
+            written: 0,
+            durability,
+        }
+    }
+
+    /// 记录一次操作
+    ///
+    /// 返回本次操作之后是否需要同步，
+    /// 需要同步的时候重置计数
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Syncer, Durability};
+    ///
+    /// let mut syncer = Syncer::new(Durability::Bytes(4096));
+    /// assert_eq!(syncer.check(1024), false);
+    /// assert_eq!(syncer.check(4096), true);
+    /// ```
+    #[rustfmt::skip]
+    pub fn check(&mut self, size: u64) -> bool {
+        self.written += size;
+        let sync = match self.durability {
+            Durability::None | Durability::OnClose => false,
+            Durability::PerObject => true,
+            Durability::Bytes(n) => self.written >= n,
+            Durability::Interval(x) => self.last.elapsed() >= x,
+        };
+
+        if sync {
+            self.reset();
+        }
+
+        sync
+    }
+
+    /// 关闭的时候是否需要同步
+    ///
+    /// 除了不主动同步的策略之外，
+    /// 关闭的时候都需要同步剩余的数据
+    pub fn on_close(&self) -> bool {
+        self.durability != Durability::None
+    }
+
+    /// 重置计数
+    pub fn reset(&mut self) {
+        self.last = Instant::now();
+        self.written = 0;
+    }
+}
@@ -1,6 +1,14 @@
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+use super::uring::Ring;
+#[cfg(feature = "memmap2")]
+use super::mmap::Map;
 use anyhow::Result;
+use std::alloc::{alloc_zeroed, dealloc, handle_alloc_error, Layout};
+use std::ops::{Deref, DerefMut};
 use std::path::Path;
 use std::io::{
+    Error,
+    ErrorKind,
     Read, 
     Write, 
     Seek, 
@@ -15,12 +23,35 @@ use std::fs::{
     File,
 };
 
+/// 直接读写对齐长度
+///
+/// 直接读写的时候位置，长度以及缓冲区地址都需要对齐
+pub const ALIGN: usize = 4096;
+
+/// 对齐缓冲区
+///
+/// 缓冲区地址按照`ALIGN`对齐，
+/// 可以直接用于直接读写
+pub struct AlignedBuffer {
+    ptr: *mut u8,
+    layout: Layout,
+    len: usize,
+}
+
 /// 文件
 ///
 /// 文件句柄抽象
 /// 内部维护写入读取缓冲区，
-/// 用于优化写入读取的系统调用
+/// 用于优化写入读取的系统调用，
+/// 启用`io-uring`特性的时候批量读写使用io_uring提交，
+/// 直接读写的时候没有对齐的读写通过对齐缓冲区中转，
+/// 启用`memmap2`特性的时候可以通过内存映射读取
 pub struct Fs {
+    #[cfg(all(feature = "io-uring", target_os = "linux"))]
+    ring: Option<Ring>,
+    #[cfg(feature = "memmap2")]
+    map: Map,
+    direct: bool,
     file: File,
     cursor: u64,
 }
@@ -28,21 +59,58 @@ pub struct Fs {
 impl Fs {
     /// 创建文件类
     ///
+    /// `direct`为真的时候使用`O_DIRECT`打开文件，
+    /// 读写绕过操作系统页缓存，
+    /// 只支持Linux
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    /// use std::path::Path;
+    ///
+    /// let fs = Fs::new("./a.text", false).unwrap();
+    /// ```
+    pub fn new<P: AsRef<Path>>(path: P, direct: bool) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true).write(true).create(true);
+        Self::open(path, options, direct)
+    }
+
+    /// 以只读方式打开文件
+    ///
+    /// 文件不存在的时候返回错误，
+    /// 不会创建文件
+    ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::Fs;
     /// use std::path::Path;
     ///
-    /// let fs = Fs::new("./a.text").unwrap();
+    /// let fs = Fs::read_only("./a.text", false).unwrap();
     /// ```
-    pub fn new<P: AsRef<Path>>(path: P) -> Result<Self> {
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .create(true)
-            .open(path)?;
-        Ok(Self { cursor: 0, file })
+    pub fn read_only<P: AsRef<Path>>(path: P, direct: bool) -> Result<Self> {
+        let mut options = OpenOptions::new();
+        options.read(true);
+        Self::open(path, options, direct)
+    }
+
+    /// 打开文件
+    fn open<P: AsRef<Path>>(path: P, mut options: OpenOptions, direct: bool) -> Result<Self> {
+        if direct {
+            set_direct(&mut options)?;
+        }
+
+        Ok(Self {
+            #[cfg(all(feature = "io-uring", target_os = "linux"))]
+            ring: Ring::new().ok(),
+            #[cfg(feature = "memmap2")]
+            map: Map::new(),
+            file: options.open(path)?,
+            cursor: 0,
+            direct,
+        })
     }
 
     /// 获取文件信息
@@ -53,7 +121,7 @@ impl Fs {
     /// use super::Fs;
     /// use std::path::Path;
     ///
-    /// let fs = Fs::new("./a.text").unwrap();
+    /// let fs = Fs::new("./a.text", false).unwrap();
     /// let metadata = fs.stat().unwrap();
     /// ```
     pub fn stat(&self) -> Result<Metadata> {
@@ -69,10 +137,14 @@ impl Fs {
     /// use std::path::Path;
     /// use bytes::Bytes;
     ///
-    /// let mut fs = Fs::new("./a.text").unwrap();
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
     /// fs.write(&Bytes::from(b"hello"), 0).unwrap();
     /// ```
     pub fn write(&mut self, chunk: &[u8], offset: u64) -> Result<()> {
+        if self.direct && !is_aligned(chunk, offset) {
+            return self.unaligned_write(chunk, offset);
+        }
+
         self.seek(offset)?;
         self.file.write_all(chunk)?;
         self.cursor_next(chunk.len());
@@ -90,7 +162,7 @@ impl Fs {
     /// use std::path::Path;
     /// use bytes::Bytes;
     ///
-    /// let mut fs = Fs::new("./a.text").unwrap();
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
     /// fs.write(&Bytes::from(b"hello"), 0).unwrap();
     /// fs.flush().unwrap();
     /// ```
@@ -99,6 +171,27 @@ impl Fs {
         Ok(())
     }
 
+    /// 同步文件数据
+    ///
+    /// 调用`fdatasync`将文件数据推入磁盘，
+    /// `flush`并不保证数据落盘
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    /// use std::path::Path;
+    /// use bytes::Bytes;
+    ///
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
+    /// fs.write(&Bytes::from(b"hello"), 0).unwrap();
+    /// fs.sync().unwrap();
+    /// ```
+    pub fn sync(&mut self) -> Result<()> {
+        self.file.sync_data()?;
+        Ok(())
+    }
+
     /// 从文件读入数据到缓冲区
     ///
     /// 读取并非完全读取指定长度，
@@ -112,10 +205,14 @@ impl Fs {
     /// use bytes::BytesMut;
     ///
     /// let buffer = [0u8; 1024];
-    /// let mut fs = Fs::new("./a.text").unwrap();
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
     /// let size = fs.read(&mut buffer, 0).unwrap();
     /// ```
     pub fn read(&mut self, chunk: &mut [u8], offset: u64) -> Result<usize> {
+        if self.direct && !is_aligned(chunk, offset) {
+            return self.unaligned_read(chunk, offset);
+        }
+
         self.seek(offset)?;
         let size = self.file.read(chunk)?;
         self.cursor_next(size);
@@ -135,16 +232,220 @@ impl Fs {
     /// use bytes::BytesMut;
     ///
     /// let buffer = [0u8; 1024];
-    /// let mut fs = Fs::new("./a.text").unwrap();
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
     /// let buffer = fs.promise_read(&mut buffer, 0).unwrap();
     /// ```
     pub fn intact_read(&mut self, chunk: &mut [u8], offset: u64) -> Result<()> {
+        if self.direct && !is_aligned(chunk, offset) {
+            return match self.unaligned_read(chunk, offset)? == chunk.len() {
+                false => Err(Error::from(ErrorKind::UnexpectedEof).into()),
+                true => Ok(())
+            };
+        }
+
         self.seek(offset)?;
         self.file.read_exact(chunk)?;
         self.cursor_next(chunk.len());
         Ok(())
     }
 
+    /// 映射读取
+    ///
+    /// 返回文件映射中指定范围的切片，
+    /// 不需要系统调用和复制，
+    /// 范围超出文件长度的时候返回`UnexpectedEof`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    ///
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
+    /// let chunk = fs.mapped_read(0, 4096).unwrap();
+    /// ```
+    #[cfg(feature = "memmap2")]
+    pub fn mapped_read(&mut self, offset: u64, len: usize) -> Result<&[u8]> {
+        self.map.get(&self.file, offset, len)
+    }
+
+    /// 复制文件句柄
+    ///
+    /// 复制的句柄和原句柄共享同一个打开的文件，
+    /// 可以在不持有轨道锁的时候读取或者发送文件数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    ///
+    /// let fs = Fs::new("./a.text", false).unwrap();
+    /// let file = fs.try_clone().unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn try_clone(&self) -> Result<File> {
+        Ok(self.file.try_clone()?)
+    }
+
+    /// 预读
+    ///
+    /// 通知操作系统即将读取指定范围，
+    /// 操作系统在后台将数据读入页缓存，
+    /// 只在Linux上生效
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    ///
+    /// let fs = Fs::new("./a.text", false).unwrap();
+    /// fs.advise(8192, 4096 * 32).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn advise(&self, offset: u64, len: u64) -> Result<()> {
+        use std::os::unix::io::AsRawFd;
+        let code = unsafe {
+            libc::posix_fadvise(
+                self.file.as_raw_fd(), 
+                offset as libc::off_t, 
+                len as libc::off_t, 
+                libc::POSIX_FADV_WILLNEED
+            )
+        };
+
+        match code {
+            0 => Ok(()),
+            _ => Err(Error::from_raw_os_error(code).into())
+        }
+    }
+
+    /// 预读
+    ///
+    /// 其他平台不做处理
+    #[cfg(not(target_os = "linux"))]
+    pub fn advise(&self, _: u64, _: u64) -> Result<()> {
+        Ok(())
+    }
+
+    /// 批量读取
+    ///
+    /// 每个缓冲区从对应的位置读满，
+    /// 启用`io-uring`特性的时候一次提交所有读取，
+    /// 内核不支持io_uring或者队列出现错误的时候逐个读取
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    ///
+    /// let mut a = [0u8; 4096];
+    /// let mut b = [0u8; 4096];
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
+    /// fs.batch_read(&mut [(0, &mut a[..]), (8192, &mut b[..])]).unwrap();
+    /// ```
+    pub fn batch_read(&mut self, chunks: &mut [(u64, &mut [u8])]) -> Result<()> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            let aligned = !self.direct || chunks.iter().all(|(o, x)| is_aligned(x, *o));
+            if let (Some(ring), true) = (self.ring.as_mut(), aligned) {
+                match ring.read(&self.file, chunks) {
+                    Err(_) if ring.is_poisoned() => self.ring = None,
+                    result => return result
+                }
+            }
+        }
+
+        for (offset, chunk) in chunks.iter_mut() {
+            self.intact_read(chunk, *offset)?;
+        }
+
+        Ok(())
+    }
+
+    /// 批量写入
+    ///
+    /// 每个缓冲区完整写入对应的位置，
+    /// 启用`io-uring`特性的时候一次提交所有写入，
+    /// 内核不支持io_uring或者队列出现错误的时候逐个写入
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Fs;
+    ///
+    /// let mut fs = Fs::new("./a.text", false).unwrap();
+    /// fs.batch_write(&[(0, &b"hello"[..]), (8192, &b"world"[..])]).unwrap();
+    /// ```
+    pub fn batch_write(&mut self, chunks: &[(u64, &[u8])]) -> Result<()> {
+        #[cfg(all(feature = "io-uring", target_os = "linux"))]
+        {
+            let aligned = !self.direct || chunks.iter().all(|(o, x)| is_aligned(x, *o));
+            if let (Some(ring), true) = (self.ring.as_mut(), aligned) {
+                match ring.write(&self.file, chunks) {
+                    Err(_) if ring.is_poisoned() => self.ring = None,
+                    result => return result
+                }
+            }
+        }
+
+        for (offset, chunk) in chunks {
+            self.write(chunk, *offset)?;
+        }
+
+        Ok(())
+    }
+
+    /// 没有对齐的读取
+    ///
+    /// 将覆盖读取范围的对齐区域读入对齐缓冲区，
+    /// 再复制需要的部分
+    fn unaligned_read(&mut self, chunk: &mut [u8], offset: u64) -> Result<usize> {
+        let start = offset - offset % ALIGN as u64;
+        let skip = (offset - start) as usize;
+        let mut buffer = AlignedBuffer::new(align_up(skip + chunk.len()));
+        let size = self.aligned_read(&mut buffer, start)?;
+        let size = std::cmp::min(size.saturating_sub(skip), chunk.len());
+        chunk[..size].copy_from_slice(&buffer[skip..skip + size]);
+        Ok(size)
+    }
+
+    /// 没有对齐的写入
+    ///
+    /// 位置和长度已经对齐的时候只需要复制到对齐缓冲区，
+    /// 否则先读取覆盖写入范围的对齐区域，
+    /// 修改之后整体写回
+    fn unaligned_write(&mut self, chunk: &[u8], offset: u64) -> Result<()> {
+        let start = offset - offset % ALIGN as u64;
+        let skip = (offset - start) as usize;
+        let mut buffer = AlignedBuffer::new(align_up(skip + chunk.len()));
+        if skip > 0 || chunk.len() % ALIGN > 0 {
+            self.aligned_read(&mut buffer, start)?;
+        }
+
+        buffer[skip..skip + chunk.len()].copy_from_slice(chunk);
+        self.seek(start)?;
+        self.file.write_all(&buffer)?;
+        self.cursor_next(buffer.len());
+        Ok(())
+    }
+
+    /// 对齐读取
+    ///
+    /// 读取直到缓冲区已满或者到达文件尾部，
+    /// 返回已经读入的长度
+    fn aligned_read(&mut self, buffer: &mut [u8], offset: u64) -> Result<usize> {
+        self.seek(offset)?;
+        let mut size = 0;
+        while size < buffer.len() {
+            match self.file.read(&mut buffer[size..])? {
+                0 => break,
+                n => size += n,
+            }
+        }
+
+        self.cursor_next(size);
+        Ok(size)
+    }
+
     /// 设置内部游标
     #[rustfmt::skip]
     fn seek(&mut self, offset: u64) -> Result<()> {
@@ -160,6 +461,81 @@ impl Fs {
     }
 }
 
+impl AlignedBuffer {
+    /// 创建对齐缓冲区
+    ///
+    /// 缓冲区内容初始化为0
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::AlignedBuffer;
+    ///
+    /// let buffer = AlignedBuffer::new(4096);
+    /// ```
+    pub fn new(len: usize) -> Self {
+        let layout = Layout::from_size_align(std::cmp::max(len, 1), ALIGN).unwrap();
+        let ptr = unsafe { alloc_zeroed(layout) };
+        if ptr.is_null() {
+            handle_alloc_error(layout);
+        }
+
+        Self {
+            layout,
+            ptr,
+            len,
+        }
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr, self.len) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr, self.len) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { dealloc(self.ptr, self.layout) }
+    }
+}
+
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+/// 检查缓冲区和位置是否对齐
+fn is_aligned(chunk: &[u8], offset: u64) -> bool {
+    offset % ALIGN as u64 == 0
+        && chunk.len() % ALIGN == 0
+        && chunk.as_ptr() as usize % ALIGN == 0
+}
+
+/// 长度向上对齐
+fn align_up(len: usize) -> usize {
+    len.div_ceil(ALIGN) * ALIGN
+}
+
+/// 设置直接读写
+#[cfg(target_os = "linux")]
+fn set_direct(options: &mut OpenOptions) -> Result<()> {
+    use std::os::unix::fs::OpenOptionsExt;
+    options.custom_flags(libc::O_DIRECT);
+    Ok(())
+}
+
+/// 设置直接读写
+#[cfg(not(target_os = "linux"))]
+fn set_direct(_: &mut OpenOptions) -> Result<()> {
+    Err(anyhow::anyhow!("direct io is only supported on linux"))
+}
+
 /// 读取目录
 ///
 /// # Examples
@@ -0,0 +1,173 @@
+use super::{index, lock, Damage, Kernel};
+use std::collections::{HashMap, HashSet};
+use anyhow::{anyhow, Result};
+
+/// 检查报告
+///
+/// `tracks` 轨道数量  
+/// `chunks` 分片总数  
+/// `referenced` 被索引引用的分片数量  
+/// `free` 失效链表中的分片数量  
+/// `orphans` 既没有被引用也不在失效链表中的分片  
+/// `double_allocated` 被重复引用或者同时在失效链表中的分片  
+/// `invalid` 引用了不存在分片的索引项  
+/// `track_errors` 轨道文件头或者失效链表的错误  
+/// `repaired` 是否已经修复
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    pub tracks: u64,
+    pub chunks: u64,
+    pub referenced: u64,
+    pub free: u64,
+    pub orphans: Vec<(u16, u64)>,
+    pub double_allocated: Vec<(u16, u64)>,
+    pub invalid: Vec<Damage>,
+    pub track_errors: Vec<(u16, String)>,
+    pub repaired: bool,
+}
+
+impl FsckReport {
+    /// 是否没有发现任何问题
+    pub fn is_clean(&self) -> bool {
+        self.orphans.is_empty()
+            && self.double_allocated.is_empty()
+            && self.invalid.is_empty()
+            && self.track_errors.is_empty()
+    }
+}
+
+impl Kernel {
+    /// 检查索引和轨道是否一致
+    ///
+    /// 遍历所有索引项的分配表，
+    /// 和每个轨道的失效链表以及文件头对比，
+    /// 找出泄露的分片和重复分配的分片，
+    /// 检查期间持有所有键的写锁，
+    /// 等待正在进行的读写完成并阻塞新的读写，
+    /// 所以正在写入的分片不会被视为泄露.
+    ///
+    /// 修复模式下将以索引为准重建失效链表，
+    /// 泄露的分片将回到失效链表，
+    /// 但是不会修改索引，
+    /// 只读实例不能修复
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let report = kernel.fsck(false).unwrap();
+    /// if !report.is_clean() {
+    ///     kernel.fsck(true).unwrap();
+    /// }
+    /// ```
+    #[rustfmt::skip]
+    pub fn fsck(&self, repair: bool) -> Result<FsckReport> {
+        if repair && self.options.read_only {
+            return Err(anyhow!("read only"));
+        }
+
+        let _guard = self.locks.all()?;
+        let mut report = FsckReport::default();
+        let tracks = self.disk.tracks();
+        let ids = tracks.ids()?;
+        
+        // 所有轨道的分片位置
+        let mut slots: HashMap<u16, HashSet<u64>> = HashMap::new();
+        for id in ids.iter() {
+            slots.insert(*id, tracks.with(*id, |track| Ok(track.slots().collect()))?);
+        }
+
+        // 收集所有被引用的分片，
+        // 检查引用是否有效以及是否重复
+        let mut referenced = HashSet::new();
+        for (key, value) in self.index.scan(b"", None) {
+            for (track_id, list) in index::decode_alloc_map(&value) {
+                for offset in list {
+                    let valid = slots
+                        .get(&track_id)
+                        .map(|x| x.contains(&offset))
+                        .unwrap_or(false);
+                    if !valid {
+                        report.invalid.push(Damage {
+                            reason: format!("invalid chunk: track {} offset {}", track_id, offset),
+                            key: key.to_vec(),
+                        });
+                    } else if !referenced.insert((track_id, offset)) {
+                        report.double_allocated.push((track_id, offset));
+                    }
+                }
+            }
+        }
+
+        // 检查每个轨道的文件头和失效链表
+    for id in ids {
+        let track = tracks.get(id)?;
+        let mut track = lock(&track)?;
+        let track_slots = &slots[&id];
+        let (free, error) = track.free_list()?;
+        let mut damaged = false;
+
+        if let Some(error) = error {
+            report.track_errors.push((id, error));
+            damaged = true;
+        }
+
+        let end = track_slots.iter().max().map(|x| x + self.options.chunk_size);
+        if let Some(end) = end {
+            if end != track.size() {
+                report.track_errors.push((id, format!(
+                    "size mismatch: header {} actual {}", 
+                    track.size(), 
+                    end
+                )));
+                damaged = true;
+            }
+        }
+
+        let free: HashSet<u64> = free.into_iter().collect();
+        for offset in free.iter() {
+            if referenced.contains(&(id, *offset)) {
+                report.double_allocated.push((id, *offset));
+                damaged = true;
+            }
+        }
+
+        let mut orphans: Vec<u64> = track_slots
+            .iter()
+            .filter(|x| !free.contains(x) && !referenced.contains(&(id, **x)))
+            .copied()
+            .collect();
+        orphans.sort_unstable();
+        if !orphans.is_empty() {
+            damaged = true;
+        }
+
+        report.tracks += 1;
+        report.chunks += track_slots.len() as u64;
+        report.free += free.len() as u64;
+        report.orphans.extend(orphans.iter().map(|x| (id, *x)));
+
+        // 以索引为准重建失效链表，
+        // 所有没有被引用的分片都视为失效分片
+        if repair && damaged {
+            let mut rebuild: Vec<u64> = track_slots
+                .iter()
+                .filter(|x| !referenced.contains(&(id, **x)))
+                .copied()
+                .collect();
+            rebuild.sort_unstable();
+            track.rebuild(&rebuild)?;
+            report.repaired = true;
+        }
+    }
+
+        report.referenced = referenced.len() as u64;
+        Ok(report)
+    }
+}
@@ -0,0 +1,157 @@
+use sha2::compress256;
+use std::io::Write;
+
+/// SHA-256初始状态
+const INITIAL_STATE: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a,
+    0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+/// 分组长度
+pub const BLOCK_SIZE: usize = 64;
+
+/// 可恢复的摘要计算
+///
+/// 计算SHA-256摘要，
+/// 可以导出已经处理的完整分组的状态，
+/// 之后从这个状态继续计算，
+/// 这样追加写入不需要重新读取已有的数据
+///
+/// `state` 已经处理的完整分组的状态  
+/// `buffer` 还没有凑满一个分组的数据  
+/// `length` 已经处理的数据长度
+#[derive(Clone)]
+pub struct Hasher {
+    state: [u32; 8],
+    buffer: Vec<u8>,
+    length: u64,
+}
+
+impl Hasher {
+    /// 从状态恢复
+    ///
+    /// `length`是状态已经处理的数据长度，
+    /// 必须是分组长度的整数倍
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Hasher;
+    ///
+    /// let hasher = Hasher::default();
+    /// let hasher = Hasher::resume(hasher.state(), 0);
+    /// ```
+    pub fn resume(state: [u32; 8], length: u64) -> Self {
+        Self {
+            buffer: Vec::with_capacity(BLOCK_SIZE),
+            length,
+            state,
+        }
+    }
+
+    /// 写入数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Hasher;
+    ///
+    /// let mut hasher = Hasher::default();
+    /// hasher.update(b"hello");
+    /// ```
+    pub fn update(&mut self, mut data: &[u8]) {
+        if !self.buffer.is_empty() {
+            let size = std::cmp::min(BLOCK_SIZE - self.buffer.len(), data.len());
+            self.buffer.extend_from_slice(&data[..size]);
+            data = &data[size..];
+            if self.buffer.len() < BLOCK_SIZE {
+                return;
+            }
+
+            let block = std::mem::take(&mut self.buffer);
+            self.compress(&block);
+        }
+
+        let size = data.len() - data.len() % BLOCK_SIZE;
+        self.compress(&data[..size]);
+        self.buffer.extend_from_slice(&data[size..]);
+    }
+
+    /// 获取状态
+    ///
+    /// 状态只包含完整的分组，
+    /// 不满一个分组的尾部数据需要在恢复之后重新写入
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Hasher;
+    ///
+    /// let mut hasher = Hasher::default();
+    /// hasher.update(b"hello");
+    /// let state = hasher.state();
+    /// ```
+    pub fn state(&self) -> [u32; 8] {
+        self.state
+    }
+
+    /// 计算摘要
+    ///
+    /// 填充之后处理剩余的分组，
+    /// 不影响当前状态
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Hasher;
+    ///
+    /// let mut hasher = Hasher::default();
+    /// hasher.update(b"hello");
+    /// let digest = hasher.finalize();
+    /// ```
+    pub fn finalize(&self) -> [u8; 32] {
+        let bits = (self.length + self.buffer.len() as u64) * 8;
+        let mut padding = self.buffer.clone();
+        padding.push(0x80);
+        while padding.len() % BLOCK_SIZE != BLOCK_SIZE - 8 {
+            padding.push(0);
+        }
+
+        padding.extend_from_slice(&bits.to_be_bytes());
+        let mut hasher = self.clone();
+        hasher.compress(&padding);
+
+        let mut digest = [0u8; 32];
+        for (chunk, word) in digest.chunks_mut(4).zip(hasher.state.iter()) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+
+        digest
+    }
+
+    /// 处理完整的分组
+    fn compress(&mut self, data: &[u8]) {
+        for block in data.chunks_exact(BLOCK_SIZE) {
+            compress256(&mut self.state, std::slice::from_ref(block.into()));
+        }
+
+        self.length += data.len() as u64;
+    }
+}
+
+impl Default for Hasher {
+    fn default() -> Self {
+        Self::resume(INITIAL_STATE, 0)
+    }
+}
+
+impl Write for Hasher {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
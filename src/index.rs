@@ -1,7 +1,14 @@
-use super::KernelOptions;
+use super::{meta, meta::ObjectMeta, IndexOptions, KernelOptions};
 use std::path::Path;
 use anyhow::Result;
-use rocksdb::DB;
+use rocksdb::{
+    BlockBasedOptions,
+    Direction, 
+    IteratorMode, 
+    WriteOptions,
+    Options,
+    DB
+};
 use bytes::{
     Buf, 
     BufMut, 
@@ -11,6 +18,16 @@ use bytes::{
 /// 分配表
 pub type AllocMap = Vec<(u16, Vec<u64>)>;
 
+/// 原始索引项
+pub type RawEntry = (Box<[u8]>, Box<[u8]>);
+
+/// 索引项标记
+///
+/// 旧版本索引项只保存了分配表，
+/// 新版本索引项以该标记开头，
+/// 随后是元数据和分配表
+const ENTRY_FLAG: u8 = 0xFF;
+
 /// 索引
 ///
 /// 索引构筑在RocksDB上，
@@ -25,9 +42,9 @@ impl Index {
     ///
     /// ```no_run
     /// use super::{Index, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
@@ -37,7 +54,11 @@ impl Index {
     pub fn new(options: &KernelOptions) -> Result<Self> {
         let path: &Path = &options.path.as_ref();
         let index_path = path.join("index");
-        Ok(Self(DB::open_default(index_path)?))
+        let db_options = db_options(&options.index);
+        Ok(Self(match options.read_only {
+            true => DB::open_for_read_only(&db_options, index_path, false)?,
+            false => DB::open(&db_options, index_path)?
+        }))
     }
 
     /// 索引是否存在
@@ -47,19 +68,19 @@ impl Index {
     /// ```no_run
     /// use super::{Index, KernelOptions};
     /// use std::collections::HashMap;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut index = Index::new(options).unwrap();
+    /// let index = Index::new(options).unwrap();
     ///
     /// let mut alloc_map = HashMap::new();
     /// alloc_map.insert(1, vec![1, 2, 3]);
     ///
-    /// index.set(b"a", &alloc_map).unwrap();
+    /// index.set(b"a", &alloc_map, &ObjectMeta::default(), false).unwrap();
     /// assert_eq!(index.has(b"a"), true);
     /// ```
     pub fn has(&self, key: &[u8]) -> Result<bool> {
@@ -68,31 +89,33 @@ impl Index {
 
     /// 删除索引
     ///
+    /// `sync`为真的时候等待写入落盘
+    ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::{Index, KernelOptions};
     /// use std::collections::HashMap;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut index = Index::new(options).unwrap();
+    /// let index = Index::new(options).unwrap();
     ///
     /// let mut alloc_map = HashMap::new();
     /// alloc_map.insert(1, vec![1, 2, 3]);
     ///
-    /// index.set(b"a", &alloc_map).unwrap();
+    /// index.set(b"a", &alloc_map, &ObjectMeta::default(), false).unwrap();
     /// assert_eq!(index.has(b"a").unwrap(), true);
     ///
-    /// index.remove(b"a").unwrap();
+    /// index.remove(b"a", false).unwrap();
     /// assert_eq!(index.has(b"a").unwrap(), false);
     /// ```
-    pub fn remove(&mut self, key: &[u8]) -> Result<()> {
-        self.0.delete(key)?;
+    pub fn remove(&self, key: &[u8], sync: bool) -> Result<()> {
+        self.0.delete_opt(key, &write_options(sync))?;
         Ok(())
     }
 
@@ -103,19 +126,19 @@ impl Index {
     /// ```no_run
     /// use super::{Index, KernelOptions};
     /// use std::collections::HashMap;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut index = Index::new(options).unwrap();
+    /// let index = Index::new(options).unwrap();
     ///
     /// let mut alloc_map = HashMap::new();
     /// alloc_map.insert(1, vec![1, 2, 3]);
     ///
-    /// index.set(b"a", &alloc_map).unwrap();
+    /// index.set(b"a", &alloc_map, &ObjectMeta::default(), false).unwrap();
     ///
     /// if let Some(value) = index.get(b"test").unwrap().get_mut(&1) {
     ///     assert_eq!(value.next(), Some(1));
@@ -128,37 +151,230 @@ impl Index {
     #[rustfmt::skip]
     pub fn get(&self, key: &[u8]) -> Result<Option<AllocMap>> {
         Ok(match self.0.get_pinned(key)? {
-            Some(x) => Some(decoder(x.as_ref())), 
+            Some(x) => Some(decode_alloc_map(x.as_ref())), 
+            None => None
+        })
+    }
+
+    /// 获取元数据
+    ///
+    /// 旧版本索引项没有保存元数据，
+    /// 这时候元数据为`None`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Index, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let index = Index::new(options).unwrap();
+    /// let meta = index.get_meta(b"a").unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn get_meta(&self, key: &[u8]) -> Result<Option<ObjectMeta>> {
+        Ok(match self.0.get_pinned(key)? {
+            Some(x) => decode_meta(x.as_ref()),
             None => None
         })
     }
 
+    /// 遍历索引
+    ///
+    /// 按照字典序遍历指定前缀的所有索引项，
+    /// 如果指定了`start_after`则从该键之后开始
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Index, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let index = Index::new(options).unwrap();
+    /// for (key, value) in index.scan(b"videos/", None) {
+    ///     println!("{:?}", key);
+    /// }
+    /// ```
+    #[rustfmt::skip]
+    pub fn scan<'a>(
+        &'a self, 
+        prefix: &'a [u8], 
+        start_after: Option<&'a [u8]>
+    ) -> impl Iterator<Item = RawEntry> + 'a {
+        let seek = match start_after {
+            Some(x) if x > prefix => x,
+            _ => prefix
+        };
+
+        self.0
+            .iterator(IteratorMode::From(seek, Direction::Forward))
+            .skip_while(move |(key, _)| Some(&key[..]) == start_after)
+            .take_while(move |(key, _)| key.starts_with(prefix))
+    }
+
     /// 写入索引项
     ///
+    /// `sync`为真的时候等待写入落盘
+    ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::{Index, KernelOptions};
     /// use std::collections::HashMap;
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut index = Index::new(options).unwrap();
+    /// let index = Index::new(options).unwrap();
     ///
     /// let mut alloc_map = HashMap::new();
     /// alloc_map.insert(1, vec![1, 2, 3]);
     ///
-    /// index.set(b"a", &alloc_map).unwrap();
+    /// index.set(b"a", &alloc_map, &ObjectMeta::default(), false).unwrap();
     /// assert_eq!(index.has(b"a").unwrap(), true);
     /// ```
-    pub fn set(&mut self, key: &[u8], value: &AllocMap) -> Result<()> {
-        self.0.put(key, &encoder(value)[..])?;
+    pub fn set(
+        &self, 
+        key: &[u8], 
+        value: &AllocMap, 
+        meta: &ObjectMeta, 
+        sync: bool
+    ) -> Result<()> {
+        self.0.put_opt(key, &encode_entry(value, meta)[..], &write_options(sync))?;
         Ok(())
     }
+
+    /// 将索引推入磁盘
+    ///
+    /// 将内存中的索引项全部写入磁盘文件
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Index, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let index = Index::new(options).unwrap();
+    /// index.flush().unwrap();
+    /// ```
+    pub fn flush(&self) -> Result<()> {
+        self.0.flush()?;
+        Ok(())
+    }
+}
+
+/// 数据库配置
+///
+/// 将索引配置转换为RocksDB配置
+#[rustfmt::skip]
+fn db_options(index: &IndexOptions) -> Options {
+    let mut options = Options::default();
+    options.create_if_missing(true);
+    
+    if let Some(size) = index.write_buffer_size {
+        options.set_write_buffer_size(size);
+    }
+
+    if let Some(count) = index.max_open_files {
+        options.set_max_open_files(count);
+    }
+
+    if let Some(count) = index.parallelism {
+        options.increase_parallelism(count);
+    }
+
+    // 块缓存和数据块长度
+    // 属于数据块表的配置
+    if index.cache_size.is_some() || index.block_size.is_some() {
+        let mut block = BlockBasedOptions::default();
+        if let Some(size) = index.cache_size {
+            block.set_lru_cache(size);
+        }
+
+        if let Some(size) = index.block_size {
+            block.set_block_size(size);
+        }
+
+        options.set_block_based_table_factory(&block);
+    }
+
+    options
+}
+
+/// 写入选项
+///
+/// 同步写入会等待预写日志落盘
+fn write_options(sync: bool) -> WriteOptions {
+    let mut options = WriteOptions::new();
+    options.set_sync(sync);
+    options
+}
+
+/// 解码索引项中的分配表
+pub fn decode_alloc_map(value: &[u8]) -> AllocMap {
+    decoder(split_entry(value).1)
+}
+
+/// 解码索引项中的元数据
+///
+/// 旧版本索引项没有元数据
+pub fn decode_meta(value: &[u8]) -> Option<ObjectMeta> {
+    split_entry(value).0.and_then(|mut x| meta::decoder(&mut x))
+}
+
+/// 拆分索引项
+///
+/// 将索引项拆分为元数据和分配表，
+/// 旧版本索引项没有元数据
+#[rustfmt::skip]
+fn split_entry(chunk: &[u8]) -> (Option<&[u8]>, &[u8]) {
+    if chunk.len() < 5 || chunk[0] != ENTRY_FLAG {
+        return (None, chunk);
+    }
+
+    let mut body = &chunk[1..];
+    let size = body.get_u32() as usize;
+    if size > body.len() {
+        return (None, &[]);
+    }
+
+    (
+        Some(&body[..size]),
+        &body[size..]
+    )
+}
+
+/// 编码索引项
+///
+/// 索引项由标记，元数据长度，
+/// 元数据以及分配表组成
+fn encode_entry(map: &AllocMap, value: &ObjectMeta) -> BytesMut {
+    let mut body = BytesMut::new();
+    meta::encoder(value, &mut body);
+
+    let mut packet = BytesMut::new();
+    packet.put_u8(ENTRY_FLAG);
+    packet.put_u32(body.len() as u32);
+    packet.extend_from_slice(&body);
+    packet.extend_from_slice(&encoder(map));
+    packet
 }
 
 /// 解码索引
@@ -0,0 +1,554 @@
+use super::{index::AllocMap, lock};
+use std::collections::HashSet;
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::path::Path;
+use anyhow::Result;
+use bytes::{
+    Buf, 
+    BufMut, 
+    BytesMut
+};
+
+use std::fs::{
+    OpenOptions,
+    File
+};
+
+/// 日志文件超过该长度并且
+/// 没有未完成的操作时清空日志
+const COMPACT_SIZE: u64 = 4 * 1024 * 1024;
+
+/// 日志记录
+///
+/// `Begin` 操作开始，保存操作的键  
+/// `Append` 追加写入开始，保存尾部分片的原始数据  
+/// `Alloc` 分配的分片，在分片写入之前记录  
+/// `Free` 释放的分片，在索引更新之前记录  
+/// `Commit` 操作完成
+#[derive(Debug, Clone, PartialEq)]
+pub enum Record {
+    Begin(u64, Vec<u8>),
+    Append(u64, Tail),
+    Alloc(u64, AllocMap),
+    Free(u64, AllocMap),
+    Commit(u64),
+}
+
+/// 尾部分片
+///
+/// 追加写入会重写尾部分片，
+/// 这里保存重写之前的状态用于回滚
+///
+/// `key` 数据的键  
+/// `size` 追加之前的数据长度  
+/// `track` 尾部分片所在轨道  
+/// `offset` 尾部分片位置  
+/// `data` 尾部分片原始数据
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tail {
+    pub key: Vec<u8>,
+    pub size: u64,
+    pub track: u16,
+    pub offset: u64,
+    pub data: Vec<u8>,
+}
+
+/// 预写日志
+///
+/// 索引和轨道文件是分开写入的，
+/// 修改之前先记录操作意图，
+/// 完成之后记录完成标记，
+/// 启动的时候根据未完成的操作恢复状态
+pub struct Journal {
+    pending: HashSet<u64>,
+    next_id: u64,
+    sync: bool,
+    size: u64,
+    file: File,
+}
+
+/// 操作
+///
+/// 持有操作ID，
+/// 离开作用域的时候写入完成标记，
+/// 这样中途返回错误的操作也会完成，
+/// 不会一直阻止日志清空
+pub struct Operation<'a> {
+    journal: &'a Mutex<Journal>,
+    done: bool,
+    id: u64,
+}
+
+impl Journal {
+    /// 打开日志
+    ///
+    /// 返回日志实例以及所有未完成的操作记录，
+    /// `sync`为假的时候记录不会主动同步到磁盘
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Journal;
+    ///
+    /// let (journal, pending) = Journal::open("./.static", true).unwrap();
+    /// ```
+    pub fn open<P: AsRef<Path>>(path: P, sync: bool) -> Result<(Self, Vec<Record>)> {
+        let mut file = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path.as_ref().join("journal"))?;
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        let records = decoder(&buffer);
+
+        // 找出没有完成标记的操作，
+        // 分片被之后的操作重新分配说明已经不属于之前的操作
+        let mut pending = Vec::new();
+        for record in records.iter() {
+            match record {
+                Record::Commit(id) => pending.retain(|x: &Record| x.id() != *id),
+                Record::Alloc(id, alloc_map) => {
+                    claim(&mut pending, *id, alloc_map);
+                    pending.push(record.clone());
+                },
+                _ => pending.push(record.clone())
+            }
+        }
+
+        let next_id = records.iter().map(|x| x.id()).max().unwrap_or(0) + 1;
+        Ok((Self {
+            size: buffer.len() as u64,
+            pending: HashSet::new(),
+            next_id,
+            sync,
+            file,
+        }, pending))
+    }
+
+    /// 开始操作
+    ///
+    /// 记录操作开始以及操作的键，
+    /// 返回操作ID
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Journal;
+    ///
+    /// let (mut journal, _) = Journal::open("./.static", true).unwrap();
+    /// let id = journal.begin(b"test").unwrap();
+    /// journal.commit(id).unwrap();
+    /// ```
+    pub fn begin(&mut self, key: &[u8]) -> Result<u64> {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.pending.insert(id);
+        self.append(&Record::Begin(id, key.to_vec()), true)?;
+        Ok(id)
+    }
+
+    /// 记录尾部分片
+    ///
+    /// 必须在重写尾部分片之前调用
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Journal, Tail};
+    ///
+    /// let (mut journal, _) = Journal::open("./.static", true).unwrap();
+    /// let id = journal.begin(b"test").unwrap();
+    /// journal.tail(id, Tail {
+    ///     key: b"test".to_vec(),
+    ///     size: 5,
+    ///     track: 1,
+    ///     offset: 24,
+    ///     data: b"hello".to_vec(),
+    /// }).unwrap();
+    /// ```
+    pub fn tail(&mut self, id: u64, tail: Tail) -> Result<()> {
+        self.append(&Record::Append(id, tail), true)
+    }
+
+    /// 记录分配的分片
+    ///
+    /// 必须在写入分片之前调用，
+    /// 恢复的时候索引没有引用的分片将被释放
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Journal;
+    ///
+    /// let (mut journal, _) = Journal::open("./.static", true).unwrap();
+    /// let id = journal.begin(b"test").unwrap();
+    /// journal.alloc(id, &vec![(1, vec![8192, 12288])]).unwrap();
+    /// ```
+    pub fn alloc(&mut self, id: u64, alloc_map: &AllocMap) -> Result<()> {
+        self.append(&Record::Alloc(id, alloc_map.clone()), true)
+    }
+
+    /// 记录释放的分片
+    ///
+    /// 必须在索引更新之前调用，
+    /// 恢复的时候索引已经不再引用这些分片则释放
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Journal;
+    ///
+    /// let (mut journal, _) = Journal::open("./.static", true).unwrap();
+    /// let id = journal.begin(b"test").unwrap();
+    /// journal.free(id, &vec![(1, vec![8192, 12288])]).unwrap();
+    /// ```
+    pub fn free(&mut self, id: u64, alloc_map: &AllocMap) -> Result<()> {
+        self.append(&Record::Free(id, alloc_map.clone()), true)
+    }
+
+    /// 完成操作
+    ///
+    /// 完成标记不需要立即落盘，
+    /// 丢失完成标记只会导致启动时多一次恢复
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Journal;
+    ///
+    /// let (mut journal, _) = Journal::open("./.static", true).unwrap();
+    /// let id = journal.begin(b"test").unwrap();
+    /// journal.commit(id).unwrap();
+    /// ```
+    pub fn commit(&mut self, id: u64) -> Result<()> {
+        self.pending.remove(&id);
+        if self.pending.is_empty() && self.size >= COMPACT_SIZE {
+            return self.reset();
+        }
+
+        self.append(&Record::Commit(id), false)
+    }
+
+    /// 清空日志
+    ///
+    /// 恢复完成之后调用，
+    /// 丢弃所有已经处理的记录
+    pub fn reset(&mut self) -> Result<()> {
+        self.file.set_len(0)?;
+        if self.sync {
+            self.file.sync_data()?;
+        }
+
+        self.size = 0;
+        Ok(())
+    }
+
+    /// 追加记录
+    fn append(&mut self, record: &Record, sync: bool) -> Result<()> {
+        let packet = encoder(record);
+        self.file.write_all(&packet)?;
+        self.size += packet.len() as u64;
+        if sync && self.sync {
+            self.file.sync_data()?;
+        }
+
+        Ok(())
+    }
+}
+
+impl<'a> Operation<'a> {
+    /// 开始操作
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Journal, Operation};
+    /// use std::sync::Mutex;
+    ///
+    /// let (journal, _) = Journal::open("./.static", true).unwrap();
+    /// let journal = Mutex::new(journal);
+    /// let operation = Operation::begin(&journal, b"test").unwrap();
+    /// operation.commit().unwrap();
+    /// ```
+    pub fn begin(journal: &'a Mutex<Journal>, key: &[u8]) -> Result<Self> {
+        let id = lock(journal)?.begin(key)?;
+        Ok(Self {
+            done: false,
+            journal,
+            id,
+        })
+    }
+
+    /// 记录尾部分片
+    pub fn tail(&self, tail: Tail) -> Result<()> {
+        lock(self.journal)?.tail(self.id, tail)
+    }
+
+    /// 记录分配的分片
+    pub fn alloc(&self, alloc_map: &AllocMap) -> Result<()> {
+        lock(self.journal)?.alloc(self.id, alloc_map)
+    }
+
+    /// 记录释放的分片
+    pub fn free(&self, alloc_map: &AllocMap) -> Result<()> {
+        lock(self.journal)?.free(self.id, alloc_map)
+    }
+
+    /// 完成操作
+    ///
+    /// 返回写入完成标记的错误，
+    /// 不调用的时候离开作用域也会完成操作
+    pub fn commit(mut self) -> Result<()> {
+        self.done = true;
+        lock(self.journal)?.commit(self.id)
+    }
+}
+
+impl Drop for Operation<'_> {
+    /// 完成操作
+    ///
+    /// 操作中途返回错误的时候写入完成标记，
+    /// 这里无法返回错误，
+    /// 丢失完成标记只会导致启动时多一次恢复
+    fn drop(&mut self) {
+        if !self.done {
+            if let Ok(mut journal) = lock(self.journal) {
+                let _ = journal.commit(self.id);
+            }
+        }
+    }
+}
+
+impl Record {
+    /// 操作ID
+    pub fn id(&self) -> u64 {
+        match self {
+            Record::Begin(id, _) => *id,
+            Record::Append(id, _) => *id,
+            Record::Alloc(id, _) => *id,
+            Record::Free(id, _) => *id,
+            Record::Commit(id) => *id,
+        }
+    }
+}
+
+/// 收回分片
+///
+/// 分片被重新分配之后，
+/// 从其他未完成操作的分配和释放记录中删除这些分片，
+/// 避免恢复的时候释放已经被其他操作使用的分片
+fn claim(pending: &mut [Record], id: u64, alloc_map: &AllocMap) {
+    let claimed: HashSet<(u16, u64)> = alloc_map
+        .iter()
+        .flat_map(|(track, list)| list.iter().map(move |x| (*track, *x)))
+        .collect();
+    for record in pending.iter_mut() {
+        match record {
+            Record::Alloc(x, map) | Record::Free(x, map) if *x != id => {
+                for (track, list) in map.iter_mut() {
+                    list.retain(|x| !claimed.contains(&(*track, *x)));
+                }
+
+                map.retain(|(_, list)| !list.is_empty());
+            },
+            _ => ()
+        }
+    }
+}
+
+/// 编码记录
+///
+/// 记录由类型，操作ID，内容长度，
+/// 内容以及校验码组成
+fn encoder(record: &Record) -> BytesMut {
+    let mut body = BytesMut::new();
+    let kind = match record {
+        Record::Commit(_) => 3,
+        Record::Begin(_, key) => {
+            body.put_u32(key.len() as u32);
+            body.extend_from_slice(key);
+            1
+        },
+        Record::Alloc(_, alloc_map) => {
+            encode_alloc_map(&mut body, alloc_map);
+            4
+        },
+        Record::Free(_, alloc_map) => {
+            encode_alloc_map(&mut body, alloc_map);
+            5
+        },
+        Record::Append(_, tail) => {
+            body.put_u32(tail.key.len() as u32);
+            body.extend_from_slice(&tail.key);
+            body.put_u64(tail.size);
+            body.put_u16(tail.track);
+            body.put_u64(tail.offset);
+            body.put_u32(tail.data.len() as u32);
+            body.extend_from_slice(&tail.data);
+            2
+        }
+    };
+
+    let mut packet = BytesMut::new();
+    packet.put_u8(kind);
+    packet.put_u64(record.id());
+    packet.put_u32(body.len() as u32);
+    packet.extend_from_slice(&body);
+    let crc = crc32c::crc32c(&packet);
+    packet.put_u32(crc);
+    packet
+}
+
+/// 解码记录
+///
+/// 遇到不完整或者校验失败的记录时停止，
+/// 这说明写入记录的时候发生了崩溃
+#[rustfmt::skip]
+fn decoder(mut chunk: &[u8]) -> Vec<Record> {
+    let mut result = Vec::new();
+
+    // 无限循环
+    // 直到缓冲区不足一个记录
+loop {
+    if chunk.len() < 17 {
+        break;
+    }
+
+    let size = u32::from_be_bytes([chunk[9], chunk[10], chunk[11], chunk[12]]) as usize;
+    if chunk.len() < 17 + size {
+        break;
+    }
+
+    let crc = u32::from_be_bytes([
+        chunk[13 + size], 
+        chunk[14 + size], 
+        chunk[15 + size], 
+        chunk[16 + size]
+    ]);
+    
+    if crc32c::crc32c(&chunk[..13 + size]) != crc {
+        break;
+    }
+
+    let kind = chunk.get_u8();
+    let id = chunk.get_u64();
+    chunk.advance(4);
+    
+    let mut body = &chunk[..size];
+    chunk.advance(size + 4);
+    
+    match kind {
+        1 => match decode_key(&mut body) {
+            Some(key) => result.push(Record::Begin(id, key)),
+            None => break
+        },
+        3 => result.push(Record::Commit(id)),
+        2 => match decode_tail(&mut body) {
+            Some(tail) => result.push(Record::Append(id, tail)),
+            None => break
+        },
+        4 => match decode_alloc_map(&mut body) {
+            Some(alloc_map) => result.push(Record::Alloc(id, alloc_map)),
+            None => break
+        },
+        5 => match decode_alloc_map(&mut body) {
+            Some(alloc_map) => result.push(Record::Free(id, alloc_map)),
+            None => break
+        },
+        _ => break
+    }
+}
+
+    result
+}
+
+/// 编码分配表
+///
+/// 分配表由段数量以及每段的轨道，
+/// 分片数量和分片位置组成
+fn encode_alloc_map(body: &mut BytesMut, alloc_map: &AllocMap) {
+    body.put_u32(alloc_map.len() as u32);
+    for (track, list) in alloc_map {
+        body.put_u16(*track);
+        body.put_u32(list.len() as u32);
+        for offset in list {
+            body.put_u64(*offset);
+        }
+    }
+}
+
+/// 解码操作的键
+#[rustfmt::skip]
+fn decode_key(body: &mut &[u8]) -> Option<Vec<u8>> {
+    if body.len() < 4 {
+        return None;
+    }
+
+    let size = body.get_u32() as usize;
+    if body.len() < size {
+        return None;
+    }
+
+    Some(body[..size].to_vec())
+}
+
+/// 解码分配表
+#[rustfmt::skip]
+fn decode_alloc_map(body: &mut &[u8]) -> Option<AllocMap> {
+    if body.len() < 4 {
+        return None;
+    }
+
+    let count = body.get_u32() as usize;
+    let mut alloc_map = Vec::new();
+    for _ in 0..count {
+        if body.len() < 6 {
+            return None;
+        }
+
+        let track = body.get_u16();
+        let size = body.get_u32() as usize;
+        if body.len() < size * 8 {
+            return None;
+        }
+
+        let list = (0..size).map(|_| body.get_u64()).collect();
+        alloc_map.push((track, list));
+    }
+
+    Some(alloc_map)
+}
+
+/// 解码尾部分片
+#[rustfmt::skip]
+fn decode_tail(body: &mut &[u8]) -> Option<Tail> {
+    if body.len() < 4 {
+        return None;
+    }
+
+    let key_size = body.get_u32() as usize;
+    if body.len() < key_size + 22 {
+        return None;
+    }
+
+    let key = body[..key_size].to_vec();
+    body.advance(key_size);
+    let size = body.get_u64();
+    let track = body.get_u16();
+    let offset = body.get_u64();
+    let data_size = body.get_u32() as usize;
+    if body.len() < data_size {
+        return None;
+    }
+
+    Some(Tail {
+        data: body[..data_size].to_vec(),
+        key,
+        size,
+        track,
+        offset,
+    })
+}
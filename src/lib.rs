@@ -21,32 +21,102 @@
 //!             |-> free chunk list first offset
 //! ```
 //! 
+//! 新版本轨道文件头包含魔数，版本，轨道ID，分片长度以及校验码，
+//! 文件头有两个槽交替写入，写入中途崩溃的时候可以从另一个槽恢复，
+//! 旧版本的24字节文件头仍然可以读取.
+//! 
 
+#[cfg(feature = "tokio")]
+mod async_kernel;
+mod cache;
 mod chunk;
 mod disk;
+mod durability;
+mod fsck;
+mod hasher;
 mod index;
+mod journal;
+mod locks;
+mod meta;
+mod migrate;
+mod options;
+mod scrub;
+mod superblock;
 mod track;
 mod fs;
+#[cfg(feature = "memmap2")]
+mod mmap;
+#[cfg(target_os = "linux")]
+mod splice;
+#[cfg(all(feature = "io-uring", target_os = "linux"))]
+mod uring;
 
 use disk::Disk;
+pub use disk::object::Object;
+pub use disk::allocator::Allocation;
+pub use meta::ObjectMeta;
+pub use chunk::{ChunkFormat, Corruption};
+pub use durability::Durability;
+pub use options::{KernelOptionsBuilder, IndexOptions};
+pub use scrub::{ScrubOptions, ScrubReport, Damage};
+pub use fsck::FsckReport;
+pub use migrate::MigrateReport;
+pub use superblock::Superblock;
+#[cfg(feature = "tokio")]
+pub use async_kernel::AsyncKernel;
 use index::Index;
+use journal::{Journal, Operation, Record, Tail};
+use hasher::Hasher;
+use durability::Syncer;
+use locks::{KeyLocks, KeyGuard};
 use anyhow::{anyhow, Result};
 use std::io::{Read, Write};
-use std::rc::Rc;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsRawFd;
+use std::sync::{
+    Arc,
+    Mutex,
+    MutexGuard
+};
 
 /// 核心配置
 ///
 /// `directory` 存储目录  
 /// `track_size` 轨道文件最大长度  
-/// `chunk_size` 分片最大长度
+/// `chunk_size` 分片最大长度  
+/// `chunk_format` 分片格式  
+/// `durability` 持久化策略  
+/// `allocation` 轨道分配策略  
+/// `direct_io` 轨道文件是否绕过页缓存  
+/// `chunk_cache_size` 分片缓存长度，为0的时候不缓存  
+/// `mmap` 是否使用内存映射读取轨道文件  
+/// `read_only` 是否以只读方式打开  
+/// `index` 索引配置
 pub struct KernelOptions {
     pub track_size: u64,
     pub chunk_size: u64,
+    pub chunk_format: ChunkFormat,
+    pub durability: Durability,
+    pub allocation: Allocation,
+    pub direct_io: bool,
+    pub chunk_cache_size: u64,
+    pub mmap: bool,
+    pub read_only: bool,
+    pub index: IndexOptions,
     pub path: String,
 }
 
 /// 存储核心
+///
+/// 实例可以在多个线程之间共享，
+/// 同一个键的写入和删除互斥，
+/// 不同轨道的读写可以同时进行，
+/// 只读实例没有日志
 pub struct Kernel {
+    options: Arc<KernelOptions>,
+    journal: Option<Mutex<Journal>>,
+    syncer: Mutex<Syncer>,
+    locks: Arc<KeyLocks>,
     disk: Disk,
     index: Index
 }
@@ -59,19 +129,97 @@ impl Kernel {
     /// ```no_run
     /// use super::Kernel;
     ///
-    /// let mut kernel = Kernel::new(
+    /// let kernel = Kernel::new(
     ///     "./.static".to_string(), 
     ///     1024 * 1024 * 1024 * 1
     /// ).unwrap();
     /// ```
     pub fn new(path: String, track_size: u64) -> Result<Self> {
-        let configure = Rc::new(KernelOptions::from(path, track_size));
+        Self::with_options(KernelOptions::from(path, track_size))
+    }
+
+    /// 使用配置创建实例
+    ///
+    /// 创建之前检查配置，
+    /// 配置无效的时候返回错误
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, KernelOptions, ChunkFormat, Durability};
+    ///
+    /// let mut options = KernelOptions::from(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// );
+    ///
+    /// options.chunk_format = ChunkFormat::Crc32c;
+    /// options.durability = Durability::PerObject;
+    /// let kernel = Kernel::with_options(options).unwrap();
+    /// ```
+    ///
+    /// 只读实例不会写入存储目录中的任何文件，
+    /// 也不会恢复未完成的操作
+    ///
+    /// ```no_run
+    /// use super::{Kernel, KernelOptions};
+    ///
+    /// let mut options = KernelOptions::load(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// options.read_only = true;
+    /// let kernel = Kernel::with_options(options).unwrap();
+    /// ```
+    pub fn with_options(options: KernelOptions) -> Result<Self> {
+        options.validate()?;
+        let configure = Arc::new(options);
+
+        // 只有每次写入都同步的时候日志才需要同步，
+        // 其他策略下轨道数据本身就可能丢失
+        let sync = configure.durability == Durability::PerObject;
         let mut disk = Disk::new(configure.clone());
         disk.init()?;
-        Ok(Self {
+        let (journal, pending) = match configure.read_only {
+            true => (None, Vec::new()),
+            false => {
+                let (journal, pending) = Journal::open(&configure.path, sync)?;
+                (Some(Mutex::new(journal)), pending)
+            }
+        };
+
+        let kernel = Self {
+            locks: Arc::new(KeyLocks::default()),
+            syncer: Mutex::new(Syncer::new(configure.durability)),
             index: Index::new(&configure)?,
+            options: configure,
+            journal,
             disk,
-        })
+        };
+
+        kernel.recover(pending)?;
+        Ok(kernel)
+    }
+
+    /// 获取超级块
+    ///
+    /// 超级块包含存储格式参数和存储目录ID
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// println!("{}", kernel.superblock().uuid);
+    /// ```
+    pub fn superblock(&self) -> &Superblock {
+        self.disk.superblock()
     }
 
     /// 读取数据
@@ -81,7 +229,7 @@ impl Kernel {
     /// ```no_run
     /// use super::Kernel;
     ///
-    /// let mut kernel = Kernel::new(
+    /// let kernel = Kernel::new(
     ///     "./.static".to_string(), 
     ///     1024 * 1024 * 1024 * 1
     /// ).unwrap();
@@ -89,21 +237,150 @@ impl Kernel {
     /// let file = std::fs::File::open("test.mp4").unwrap();
     /// kernel.read(b"test", file).unwrap();
     /// ```
-    pub fn read(&mut self, key: &[u8], stream: impl Write) -> Result<()> {
+    pub fn read(&self, key: &[u8], stream: impl Write) -> Result<()> {
+        let _guard = self.read_lock(key)?;
         match self.index.get(key)? {
             Some(x) => self.disk.read(stream, x),
             _ => Err(anyhow!("not found")),
         }
     }
 
+    /// 读取指定范围的数据
+    ///
+    /// 从`offset`开始读取最多`len`长度，
+    /// 范围超出数据尾部的部分将被忽略
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let file = std::fs::File::create("part.mp4").unwrap();
+    /// kernel.read_range(b"test", 1024, 4096, file).unwrap();
+    /// ```
+    pub fn read_range(
+        &self,
+        key: &[u8],
+        offset: u64,
+        len: u64,
+        stream: impl Write
+    ) -> Result<()> {
+        let _guard = self.read_lock(key)?;
+        match self.index.get(key)? {
+            Some(x) => self.disk.read_range(stream, x, offset, len),
+            _ => Err(anyhow!("not found")),
+        }
+    }
+
+    /// 零拷贝发送数据
+    ///
+    /// 将数据从轨道文件直接发送到套接字或者管道，
+    /// 数据不经过用户空间，
+    /// 不带校验的分片格式除尾部分片之外不会校验，
+    /// 带校验的分片格式发送之前会读取并校验每个分片，
+    /// 只支持Linux
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    /// use std::net::TcpStream;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+    /// kernel.splice(b"test", &stream).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn splice(&self, key: &[u8], stream: &impl AsRawFd) -> Result<()> {
+        self.splice_range(key, 0, u64::MAX, stream)
+    }
+
+    /// 零拷贝发送指定范围的数据
+    ///
+    /// 从`offset`开始发送最多`len`长度，
+    /// 范围超出数据尾部的部分将被忽略
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    /// use std::net::TcpStream;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+    /// kernel.splice_range(b"test", 1024, 4096, &stream).unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn splice_range(
+        &self,
+        key: &[u8],
+        offset: u64,
+        len: u64,
+        stream: &impl AsRawFd
+    ) -> Result<()> {
+        // 发送的时候不持有轨道锁，
+        // 键的读锁保证分片在发送过程中不会被释放
+        let _guard = self.read_lock(key)?;
+        match self.index.get(key)? {
+            Some(x) => self.disk.splice_range(stream.as_raw_fd(), x, offset, len),
+            _ => Err(anyhow!("not found")),
+        }
+    }
+
+    /// 打开对象句柄
+    ///
+    /// 对象句柄实现了`Read`和`Seek`，
+    /// 可以随机访问数据，
+    /// 句柄在存活期间持有键的读锁，
+    /// 数据不会被替换或者删除，
+    /// 持有句柄的线程修改同一个键会死锁
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    /// use std::io::{Read, Seek, SeekFrom};
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let mut object = kernel.open(b"test").unwrap();
+    /// object.seek(SeekFrom::Start(1024)).unwrap();
+    ///
+    /// let mut buf = [0u8; 4096];
+    /// object.read(&mut buf).unwrap();
+    /// ```
+    pub fn open(&self, key: &[u8]) -> Result<Object> {
+        let guard = self.read_lock(key)?;
+        match self.index.get(key)? {
+            Some(x) => Ok(self.disk.open(x)?.hold(guard)),
+            _ => Err(anyhow!("not found")),
+        }
+    }
+
     /// 写入数据
     ///
     /// # Examples
     ///
-    // ```no_run
+    /// ```no_run
     /// use super::Kernel;
     ///
-    /// let mut kernel = Kernel::new(
+    /// let kernel = Kernel::new(
     ///     "./.static".to_string(), 
     ///     1024 * 1024 * 1024 * 1
     /// ).unwrap();
@@ -111,43 +388,638 @@ impl Kernel {
     /// let file = std::fs::File::open("test.mp4").unwrap();
     /// kernel.write(b"test", file).unwrap();
     /// ```
+    pub fn write(&self, key: &[u8], stream: impl Read) -> Result<()> {
+        self.write_with_meta(key, stream, ObjectMeta::default())
+    }
+
+    /// 写入数据以及元数据
+    ///
+    /// 元数据中的长度，分片数量和时间
+    /// 由内部填充，外部传入的值将被忽略
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, ObjectMeta};
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let meta = ObjectMeta::new(Some("video/mp4".to_string()))
+    ///     .header("owner", "panda");
+    ///
+    /// let file = std::fs::File::open("test.mp4").unwrap();
+    /// kernel.write_with_meta(b"test", file, meta).unwrap();
+    /// ```
+    pub fn write_with_meta(
+        &self, 
+        key: &[u8], 
+        stream: impl Read, 
+        meta: ObjectMeta
+    ) -> Result<()> {
+        let _guard = self.write_lock(key)?;
+        self.insert(key, stream, meta, false)
+    }
+
+    /// 写入或者替换数据
+    ///
+    /// 和`write`不同，
+    /// 如果数据已经存在则替换数据，
+    /// 替换过程中旧数据始终可以读取
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let file = std::fs::File::open("test.mp4").unwrap();
+    /// kernel.put(b"test", file).unwrap();
+    /// ```
+    pub fn put(&self, key: &[u8], stream: impl Read) -> Result<()> {
+        self.put_with_meta(key, stream, ObjectMeta::default())
+    }
+
+    /// 写入或者替换数据以及元数据
+    ///
+    /// 替换的时候保留原有的创建时间
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, ObjectMeta};
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let meta = ObjectMeta::new(Some("video/mp4".to_string()));
+    /// let file = std::fs::File::open("test.mp4").unwrap();
+    /// kernel.put_with_meta(b"test", file, meta).unwrap();
+    /// ```
+    pub fn put_with_meta(
+        &self, 
+        key: &[u8], 
+        stream: impl Read, 
+        meta: ObjectMeta
+    ) -> Result<()> {
+        let _guard = self.write_lock(key)?;
+        self.insert(key, stream, meta, true)
+    }
+
+    /// 追加数据
+    ///
+    /// 将数据追加到已有数据的尾部，
+    /// 已有的分片不会被重写，
+    /// 摘要从元数据中保存的摘要状态继续计算，
+    /// 只需要读取不满一个分组的尾部数据，
+    /// 旧版本数据没有摘要状态，
+    /// 第一次追加的时候读取全部已有数据计算摘要，
+    /// 如果数据不存在则创建数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// kernel.append(b"test.log", &b"hello"[..]).unwrap();
+    /// kernel.append(b"test.log", &b" world"[..]).unwrap();
+    /// ```
     #[rustfmt::skip]
-    pub fn write(&mut self, key: &[u8], stream: impl Read) -> Result<()> {
-        if self.index.has(key)? { return Err(anyhow!("not empty")); }
-        self.index.set(key, &self.disk.write(stream)?)
+    pub fn append(&self, key: &[u8], stream: impl Read) -> Result<()> {
+        let _guard = self.write_lock(key)?;
+        let alloc_map = match self.index.get(key)? {
+            None => return self.insert(key, stream, ObjectMeta::default(), false),
+            Some(x) => x
+        };
+
+        let mut meta = match self.index.get_meta(key)? {
+            None => self.legacy_meta(alloc_map.clone())?,
+            Some(x) => x
+        };
+
+        let hasher = self.hasher(&alloc_map, &meta)?;
+
+        // 追加写入会重写尾部分片，
+        // 重写之前先记录尾部分片的原始数据
+        let operation = Operation::begin(self.journal()?, key)?;
+        if let Some((track, offset, data)) = self.disk.tail(&alloc_map)? {
+            operation.tail(Tail {
+                key: key.to_vec(),
+                size: meta.size,
+                track,
+                offset,
+                data,
+            })?;
+        }
+
+        let intent = |x: &index::AllocMap| operation.alloc(x);
+        let written = self.disk.append(alloc_map, stream, &intent, hasher)?;
+        meta.size += written.size;
+        meta.chunks = written.alloc_map.iter().map(|(_, x)| x.len() as u64).sum();
+        meta.update_time = meta::now();
+        meta.digest = Some(written.digest);
+        meta.state = Some(written.state);
+        let sync = self.sync(written.size, &written.alloc_map)?;
+        self.index.set(key, &written.alloc_map, &meta, sync)?;
+        operation.commit()
+    }
+
+    /// 获取元数据
+    ///
+    /// 元数据中的摘要总是覆盖完整数据，
+    /// 追加写入之后同样可以作为ETag，
+    /// 旧版本数据没有保存元数据，
+    /// 这时候从轨道中计算长度和分片数量，
+    /// 没有摘要
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let meta = kernel.stat(b"test").unwrap();
+    /// println!("{}", meta.size);
+    /// ```
+    pub fn stat(&self, key: &[u8]) -> Result<ObjectMeta> {
+        let _guard = self.read_lock(key)?;
+        if let Some(meta) = self.index.get_meta(key)? {
+            return Ok(meta);
+        }
+
+        match self.index.get(key)? {
+            Some(x) => self.legacy_meta(x),
+            None => Err(anyhow!("not found")),
+        }
+    }
+
+    /// 列出键
+    ///
+    /// 按照字典序列出指定前缀的键，
+    /// 从`start_after`之后开始，最多返回`limit`个，
+    /// 可以使用上次返回的最后一个键继续列出
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let keys = kernel.list(b"videos/", None, 100).unwrap();
+    /// let next = kernel.list(b"videos/", keys.last().map(|x| &x[..]), 100).unwrap();
+    /// ```
+    pub fn list(
+        &self,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize
+    ) -> Result<Vec<Vec<u8>>> {
+        Ok(self.index
+            .scan(prefix, start_after)
+            .take(limit)
+            .map(|(key, _)| key.to_vec())
+            .collect())
+    }
+
+    /// 列出键以及元数据
+    ///
+    /// 和`list`相同，
+    /// 但是同时返回每个键的元数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// for (key, meta) in kernel.list_with_meta(b"videos/", None, 100).unwrap() {
+    ///     println!("{:?} {}", key, meta.size);
+    /// }
+    /// ```
+    pub fn list_with_meta(
+        &self,
+        prefix: &[u8],
+        start_after: Option<&[u8]>,
+        limit: usize
+    ) -> Result<Vec<(Vec<u8>, ObjectMeta)>> {
+        let entries: Vec<_> = self.index
+            .scan(prefix, start_after)
+            .take(limit)
+            .collect();
+
+        let mut result = Vec::with_capacity(entries.len());
+        for (key, value) in entries {
+            let meta = match index::decode_meta(&value) {
+                None => self.legacy_meta(index::decode_alloc_map(&value))?,
+                Some(x) => x,
+            };
+
+            result.push((key.to_vec(), meta));
+        }
+
+        Ok(result)
     }
 
     /// 删除数据
     ///
+    /// 先记录需要释放的分片，
+    /// 然后删除索引项，最后释放分片，
+    /// 中途崩溃的时候启动时根据索引决定是否释放
+    ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::Kernel;
     ///
-    /// let mut kernel = Kernel::new(
+    /// let kernel = Kernel::new(
     ///     "./.static".to_string(), 
     ///     1024 * 1024 * 1024 * 1
     /// ).unwrap();
     ///
     /// kernel.delete(b"test").unwrap();
     /// ```
-    pub fn delete(&mut self, key: &[u8]) -> Result<()> {
+    pub fn delete(&self, key: &[u8]) -> Result<()> {
+        let _guard = self.write_lock(key)?;
         match self.index.get(key)? {
             None => Err(anyhow!("not found")),
             Some(x) => {
+                let operation = Operation::begin(self.journal()?, key)?;
+                operation.free(&x)?;
+                let sync = lock(&self.syncer)?.check(0);
+                self.index.remove(key, sync)?;
                 self.disk.remove(&x)?;
-                self.index.remove(key)
+                if sync {
+                    self.sync_disk(&x)?;
+                }
+
+                operation.commit()
             }
         }
     }
 }
 
+impl Kernel {
+    /// 写入数据
+    ///
+    /// 先写入新的分片，然后替换索引项，
+    /// 索引项替换是原子操作，
+    /// 最后才释放旧的分片
+    #[rustfmt::skip]
+    fn insert(
+        &self, 
+        key: &[u8], 
+        stream: impl Read, 
+        mut meta: ObjectMeta, 
+        replace: bool
+    ) -> Result<()> {
+        if !replace && self.index.has(key)? {
+            return Err(anyhow!("not empty"));
+        }
+
+        let previous = match replace {
+            true => self.index.get(key)?,
+            false => None
+        };
+
+        meta.create_time = match self.index.get_meta(key)? {
+            Some(x) if replace => x.create_time,
+            _ => meta::now()
+        };
+
+        meta.update_time = meta::now();
+        meta.digest = None;
+        self.store(key, stream, meta, previous)
+    }
+
+    /// 写入分片并替换索引项
+    ///
+    /// 元数据中的时间由外部决定，
+    /// 如果元数据带有摘要则校验写入数据的摘要，
+    /// 摘要不一致的时候释放新的分片并返回错误
+    #[rustfmt::skip]
+    fn store(
+        &self, 
+        key: &[u8], 
+        stream: impl Read, 
+        mut meta: ObjectMeta, 
+        previous: Option<index::AllocMap>
+    ) -> Result<()> {
+        let operation = Operation::begin(self.journal()?, key)?;
+        let intent = |x: &index::AllocMap| operation.alloc(x);
+        let written = self.disk.write(stream, &intent)?;
+        if meta.digest.map(|x| x != written.digest).unwrap_or(false) {
+            self.disk.remove(&written.alloc_map)?;
+            return Err(anyhow!("digest mismatch"));
+        }
+
+        meta.size = written.size;
+        meta.chunks = written.alloc_map.iter().map(|(_, x)| x.len() as u64).sum();
+        meta.digest = Some(written.digest);
+        meta.state = Some(written.state);
+
+        // 替换索引之前记录需要释放的旧分片，
+        // 索引已经指向新的分片之后才释放
+        if let Some(x) = previous.as_ref() {
+            operation.free(x)?;
+        }
+
+        let sync = self.sync(written.size, &written.alloc_map)?;
+        self.index.set(key, &written.alloc_map, &meta, sync)?;
+        if let Some(x) = previous {
+            self.disk.remove(&x)?;
+        }
+
+        operation.commit()
+    }
+
+    /// 根据持久化策略同步轨道
+    ///
+    /// 需要同步的时候先同步轨道文件，
+    /// 返回索引是否需要同步写入
+    fn sync(&self, size: u64, alloc_map: &index::AllocMap) -> Result<bool> {
+        let sync = lock(&self.syncer)?.check(size);
+        if sync {
+            self.sync_disk(alloc_map)?;
+        }
+
+        Ok(sync)
+    }
+
+    /// 同步轨道文件
+    ///
+    /// 每次写入都同步的时候只同步这次操作涉及的轨道，
+    /// 其他策略同步的是上次同步之后的所有写入，
+    /// 所以同步所有轨道
+    fn sync_disk(&self, alloc_map: &index::AllocMap) -> Result<()> {
+        match self.options.durability {
+            Durability::PerObject => self.disk.sync_tracks(alloc_map),
+            _ => self.disk.sync()
+        }
+    }
+
+    /// 恢复未完成的操作
+    ///
+    /// 根据索引判断每个操作是否已经生效，
+    /// 已经生效的操作释放记录中需要释放的分片，
+    /// 没有生效的操作还原尾部分片并释放已经分配的分片
+    #[rustfmt::skip]
+    fn recover(&self, pending: Vec<Record>) -> Result<()> {
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        // 按照操作ID分组，
+        // 保持记录的顺序
+        let mut operations: Vec<(u64, Vec<Record>)> = Vec::new();
+        for record in pending {
+            match operations.iter_mut().find(|(id, _)| *id == record.id()) {
+                Some((_, records)) => records.push(record),
+                None => operations.push((record.id(), vec![record]))
+            }
+        }
+
+        for (_, records) in operations {
+            let mut key = None;
+            let mut tail = None;
+            let mut allocated = Vec::new();
+            let mut freed = Vec::new();
+            for record in records {
+                match record {
+                    Record::Begin(_, x) => key = Some(x),
+                    Record::Append(_, x) => tail = Some(x),
+                    Record::Alloc(_, x) => allocated.extend(x),
+                    Record::Free(_, x) => freed.extend(x),
+                    Record::Commit(_) => ()
+                }
+            }
+
+            // 开始记录总是第一个写入，
+            // 没有开始记录说明日志已经损坏
+            let key = match key {
+                Some(x) => x,
+                None => continue
+            };
+
+            let applied = self.applied(&key, &allocated, &freed, tail.as_ref())?;
+            if !applied {
+                if let Some(tail) = tail {
+                    self.disk.restore(tail.track, tail.offset, &tail.data)?;
+                }
+            }
+
+            self.disk.release(if applied { &freed } else { &allocated })?;
+        }
+
+        lock(self.journal()?)?.reset()
+    }
+
+    /// 操作是否已经生效
+    ///
+    /// 索引更新是操作唯一的提交点，
+    /// 索引引用了第一个分配的分片，
+    /// 或者不再引用第一个释放的分片的时候说明已经生效，
+    /// 没有分配和释放的追加写入比较索引中的长度，
+    /// 生效的追加写入总是会写入元数据，
+    /// 所以没有元数据的旧版本索引项说明还没有生效
+    #[rustfmt::skip]
+    fn applied(
+        &self, 
+        key: &[u8], 
+        allocated: &index::AllocMap, 
+        freed: &index::AllocMap,
+        tail: Option<&Tail>
+    ) -> Result<bool> {
+        let alloc_map = self.index.get(key)?;
+        let references = |(track, list): &(u16, Vec<u64>)| alloc_map
+            .as_ref()
+            .map(|x| x.iter().any(|(id, l)| id == track && l.contains(&list[0])))
+            .unwrap_or(false);
+
+        if let Some(first) = allocated.iter().find(|(_, x)| !x.is_empty()) {
+            return Ok(references(first));
+        }
+
+        if let Some(first) = freed.iter().find(|(_, x)| !x.is_empty()) {
+            return Ok(!references(first));
+        }
+
+        Ok(match (tail, self.index.get_meta(key)?) {
+            (Some(tail), Some(meta)) => meta.size != tail.size,
+            _ => false
+        })
+    }
+
+    /// 获取日志
+    ///
+    /// 只读实例没有日志，
+    /// 所有修改操作都在这里返回错误
+    fn journal(&self) -> Result<&Mutex<Journal>> {
+        self.journal.as_ref().ok_or_else(|| anyhow!("read only"))
+    }
+
+    /// 获取键的读锁
+    ///
+    /// 读取的时候持有读锁，
+    /// 避免读取过程中分片被释放
+    fn read_lock(&self, key: &[u8]) -> Result<KeyGuard> {
+        self.locks.read(key)
+    }
+
+    /// 获取键的写锁
+    ///
+    /// 写入，追加以及删除的时候持有写锁，
+    /// 同一个键的修改依次进行
+    fn write_lock(&self, key: &[u8]) -> Result<KeyGuard> {
+        self.locks.write(key)
+    }
+
+    /// 恢复摘要计算
+    ///
+    /// 元数据保存了摘要状态的时候
+    /// 只需要读取不满一个分组的尾部数据，
+    /// 否则读取全部已有数据重新计算
+    fn hasher(&self, alloc_map: &index::AllocMap, meta: &ObjectMeta) -> Result<Hasher> {
+        let (mut hasher, offset) = match meta.state {
+            None => (Hasher::default(), 0),
+            Some(state) => {
+                let offset = meta.size - meta.size % hasher::BLOCK_SIZE as u64;
+                (Hasher::resume(state, offset), offset)
+            }
+        };
+
+        self.disk.read_range(&mut hasher, alloc_map.clone(), offset, u64::MAX)?;
+        Ok(hasher)
+    }
+
+    /// 计算旧版本数据的元数据
+    ///
+    /// 旧版本索引项只保存了分配表，
+    /// 长度需要读取尾部分片得到
+    fn legacy_meta(&self, alloc_map: index::AllocMap) -> Result<ObjectMeta> {
+        let chunks = alloc_map.iter().map(|(_, x)| x.len() as u64).sum();
+        Ok(ObjectMeta {
+            size: self.disk.open(alloc_map)?.len(),
+            chunks,
+            ..Default::default()
+        })
+    }
+}
+
+impl Drop for Kernel {
+    /// 关闭实例
+    ///
+    /// 按照持久化策略同步剩余的数据
+    fn drop(&mut self) {
+        let on_close = self.syncer
+            .get_mut()
+            .map(|x| x.on_close())
+            .unwrap_or(false);
+        if on_close {
+            let _ = self.disk.sync();
+            let _ = self.index.flush();
+        }
+    }
+}
+
+/// 获取互斥锁
+///
+/// 持有锁的线程崩溃之后返回错误
+pub(crate) fn lock<T>(mutex: &Mutex<T>) -> Result<MutexGuard<'_, T>> {
+    mutex.lock().map_err(|_| anyhow!("lock poisoned"))
+}
+
 impl KernelOptions {
+    /// 创建配置构建器
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, KernelOptions};
+    ///
+    /// let options = KernelOptions::builder()
+    ///     .path("./.static")
+    ///     .chunk_size(8192)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let kernel = Kernel::with_options(options).unwrap();
+    /// ```
+    pub fn builder() -> KernelOptionsBuilder {
+        KernelOptionsBuilder::default()
+    }
+
     pub fn from(path: String, track_size: u64) -> Self {
         Self {
+            chunk_format: ChunkFormat::Plain,
+            durability: Durability::None,
+            allocation: Allocation::Sequential,
+            index: IndexOptions::default(),
+            chunk_cache_size: 0,
+            direct_io: false,
+            mmap: false,
+            read_only: false,
             chunk_size: 4096,
             track_size,
             path,
         }
     }
+
+    /// 根据存储目录创建配置
+    ///
+    /// 存储目录有超级块的时候使用超级块中的格式参数，
+    /// 否则按照旧版本格式使用指定的轨道长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::KernelOptions;
+    ///
+    /// let options = KernelOptions::load(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    /// ```
+    pub fn load(path: String, track_size: u64) -> Result<Self> {
+        let superblock = Superblock::load(&path)?;
+        let mut options = Self::from(path, track_size);
+        if let Some(superblock) = superblock {
+            options.chunk_size = superblock.chunk_size;
+            options.track_size = superblock.track_size;
+            options.chunk_format = superblock.chunk_format;
+        }
+
+        Ok(options)
+    }
+
+    /// 分片内部最大数据长度
+    ///
+    /// 分片长度减去分片头长度
+    pub fn diff_size(&self) -> u64 {
+        self.chunk_size - self.chunk_format.header_size()
+    }
 }
@@ -0,0 +1,223 @@
+use super::lock;
+use anyhow::{anyhow, Result};
+use std::collections::HashMap;
+use std::sync::{
+    Arc,
+    Condvar,
+    Mutex
+};
+
+/// 键锁状态
+///
+/// `readers` 持有读锁的数量
+/// `writer` 是否持有写锁
+/// `refs` 持有或者等待锁的数量
+/// `condvar` 等待这个键的线程
+struct Entry {
+    readers: usize,
+    writer: bool,
+    refs: usize,
+    condvar: Arc<Condvar>,
+}
+
+/// 键锁状态表
+///
+/// `entries` 每个键的锁状态
+/// `holders` 持有或者等待键锁的数量
+/// `exclusive` 是否持有或者等待全局写锁
+#[derive(Default)]
+struct State {
+    entries: HashMap<Vec<u8>, Entry>,
+    holders: usize,
+    exclusive: bool,
+}
+
+/// 键锁
+///
+/// 每个键拥有独立的读写锁，
+/// 不同的键之间不会互相阻塞，
+/// 没有线程持有或者等待的时候删除这个键的状态，
+/// 所以状态数量不会随着键的数量增长.
+///
+/// 持有任何键锁的时候同时计入全局持有数量，
+/// 获取全局写锁相当于获取所有键的写锁，
+/// 键锁守卫持有键锁的引用计数，
+/// 所以守卫可以保存在对象句柄中
+#[derive(Default)]
+pub struct KeyLocks {
+    state: Mutex<State>,
+    condvar: Condvar,
+}
+
+/// 键锁守卫
+///
+/// 离开作用域的时候释放锁，
+/// 键锁释放之后才减少全局持有数量
+pub struct KeyGuard {
+    locks: Arc<KeyLocks>,
+    key: Vec<u8>,
+    write: bool,
+}
+
+/// 全局写锁守卫
+///
+/// 离开作用域的时候释放全局写锁，
+/// 唤醒等待获取键锁的线程
+pub struct GlobalGuard<'a> {
+    locks: &'a KeyLocks,
+}
+
+impl KeyLocks {
+    /// 获取读锁
+    ///
+    /// 其他线程持有写锁的时候等待
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::KeyLocks;
+    /// use std::sync::Arc;
+    ///
+    /// let locks = Arc::new(KeyLocks::default());
+    /// let guard = locks.read(b"test").unwrap();
+    /// ```
+    pub fn read(self: &Arc<Self>, key: &[u8]) -> Result<KeyGuard> {
+        self.acquire(key, false)
+    }
+
+    /// 获取写锁
+    ///
+    /// 其他线程持有读锁或者写锁的时候等待
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::KeyLocks;
+    /// use std::sync::Arc;
+    ///
+    /// let locks = Arc::new(KeyLocks::default());
+    /// let guard = locks.write(b"test").unwrap();
+    /// ```
+    pub fn write(self: &Arc<Self>, key: &[u8]) -> Result<KeyGuard> {
+        self.acquire(key, true)
+    }
+
+    /// 获取所有键的写锁
+    ///
+    /// 等待所有持有键锁的线程释放，
+    /// 等待和持有期间其他线程不能获取任何键锁
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::KeyLocks;
+    ///
+    /// let locks = KeyLocks::default();
+    /// let guard = locks.all().unwrap();
+    /// ```
+    pub fn all(&self) -> Result<GlobalGuard<'_>> {
+        let mut state = lock(&self.state)?;
+        while state.exclusive {
+            state = self.condvar.wait(state).map_err(|_| anyhow!("lock poisoned"))?;
+        }
+
+        state.exclusive = true;
+        while state.holders > 0 {
+            state = self.condvar.wait(state).map_err(|_| anyhow!("lock poisoned"))?;
+        }
+
+        Ok(GlobalGuard { locks: self })
+    }
+
+    /// 获取锁
+    ///
+    /// 先等待全局写锁释放，
+    /// 然后增加键的引用数量，
+    /// 等待的时候状态不会被删除
+    #[rustfmt::skip]
+    fn acquire(self: &Arc<Self>, key: &[u8], write: bool) -> Result<KeyGuard> {
+        let mut state = lock(&self.state)?;
+        while state.exclusive {
+            state = self.condvar.wait(state).map_err(|_| anyhow!("lock poisoned"))?;
+        }
+
+        state.holders += 1;
+        let entry = state.entries.entry(key.to_vec()).or_insert_with(|| Entry {
+            condvar: Arc::new(Condvar::new()),
+            writer: false,
+            readers: 0,
+            refs: 0,
+        });
+
+        entry.refs += 1;
+        let condvar = entry.condvar.clone();
+
+        // 等待直到可以获取锁，
+        // 唤醒之后重新检查状态
+    loop {
+        let entry = state.entries.get_mut(key).ok_or_else(|| anyhow!("key lock missing"))?;
+        if !entry.writer && (!write || entry.readers == 0) {
+            match write {
+                true => entry.writer = true,
+                false => entry.readers += 1
+            }
+
+            break;
+        }
+
+        state = condvar.wait(state).map_err(|_| anyhow!("lock poisoned"))?;
+    }
+
+        Ok(KeyGuard {
+            locks: self.clone(),
+            key: key.to_vec(),
+            write,
+        })
+    }
+}
+
+impl Drop for KeyGuard {
+    /// 释放锁
+    ///
+    /// 没有其他线程持有或者等待的时候删除状态，
+    /// 否则唤醒等待这个键的线程，
+    /// 最后一个键锁释放的时候唤醒等待全局写锁的线程
+    fn drop(&mut self) {
+        let mut state = match self.locks.state.lock() {
+            Ok(x) => x,
+            Err(x) => x.into_inner()
+        };
+
+        if let Some(entry) = state.entries.get_mut(&self.key) {
+            match self.write {
+                true => entry.writer = false,
+                false => entry.readers -= 1
+            }
+
+            entry.refs -= 1;
+            if entry.refs == 0 {
+                state.entries.remove(&self.key);
+            } else {
+                entry.condvar.notify_all();
+            }
+        }
+
+        state.holders -= 1;
+        if state.holders == 0 && state.exclusive {
+            self.locks.condvar.notify_all();
+        }
+    }
+}
+
+impl Drop for GlobalGuard<'_> {
+    /// 释放全局写锁
+    fn drop(&mut self) {
+        let mut state = match self.locks.state.lock() {
+            Ok(x) => x,
+            Err(x) => x.into_inner()
+        };
+
+        state.exclusive = false;
+        self.locks.condvar.notify_all();
+    }
+}
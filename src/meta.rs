@@ -0,0 +1,217 @@
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use bytes::{
+    Buf, 
+    BufMut, 
+    BytesMut
+};
+
+/// 对象元数据
+///
+/// `size` 数据长度  
+/// `chunks` 分片数量  
+/// `create_time` 创建时间(毫秒)  
+/// `update_time` 修改时间(毫秒)  
+/// `content_type` 内容类型  
+/// `headers` 自定义头  
+/// `digest` 数据摘要(SHA-256)  
+/// `state` 摘要计算状态，由内部填充，用于追加写入之后继续计算摘要
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ObjectMeta {
+    pub size: u64,
+    pub chunks: u64,
+    pub create_time: u64,
+    pub update_time: u64,
+    pub content_type: Option<String>,
+    pub headers: HashMap<String, String>,
+    pub digest: Option<[u8; 32]>,
+    pub state: Option<[u32; 8]>,
+}
+
+impl ObjectMeta {
+    /// 创建元数据
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::ObjectMeta;
+    ///
+    /// let meta = ObjectMeta::new(Some("video/mp4".to_string()));
+    /// ```
+    pub fn new(content_type: Option<String>) -> Self {
+        Self {
+            content_type,
+            ..Default::default()
+        }
+    }
+
+    /// 添加自定义头
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::ObjectMeta;
+    ///
+    /// let meta = ObjectMeta::new(None)
+    ///     .header("owner", "panda");
+    /// ```
+    pub fn header(mut self, key: &str, value: &str) -> Self {
+        self.headers.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// 获取ETag
+    ///
+    /// 数据摘要的十六进制形式，
+    /// 追加写入之后是完整数据的摘要，
+    /// 旧版本数据没有摘要的时候返回`None`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::ObjectMeta;
+    ///
+    /// let meta = ObjectMeta::new(None);
+    /// assert_eq!(meta.etag(), None);
+    /// ```
+    pub fn etag(&self) -> Option<String> {
+        self.digest.map(|digest| {
+            digest.iter()
+                .map(|x| format!("{:02x}", x))
+                .collect()
+        })
+    }
+}
+
+/// 获取当前时间
+///
+/// 返回UNIX时间戳(毫秒)
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|x| x.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// 编码元数据
+///
+/// 将元数据转为
+/// 字节缓冲区
+pub fn encoder(meta: &ObjectMeta, packet: &mut BytesMut) {
+    packet.put_u64(meta.size);
+    packet.put_u64(meta.chunks);
+    packet.put_u64(meta.create_time);
+    packet.put_u64(meta.update_time);
+    put_str(packet, meta.content_type.as_deref().unwrap_or(""));
+    packet.put_u32(meta.headers.len() as u32);
+    for (key, value) in meta.headers.iter() {
+        put_str(packet, key);
+        put_str(packet, value);
+    }
+
+    match meta.digest {
+        None => packet.put_u8(0),
+        Some(digest) => {
+            packet.put_u8(1);
+            packet.extend_from_slice(&digest);
+        }
+    }
+
+    match meta.state {
+        None => packet.put_u8(0),
+        Some(state) => {
+            packet.put_u8(1);
+            for word in state.iter() {
+                packet.put_u32(*word);
+            }
+        }
+    }
+}
+
+/// 解码元数据
+///
+/// 将缓冲区转为元数据，
+/// 缓冲区不完整的时候返回`None`
+#[rustfmt::skip]
+pub fn decoder(chunk: &mut &[u8]) -> Option<ObjectMeta> {
+    if chunk.len() < 36 {
+        return None;
+    }
+
+    let size = chunk.get_u64();
+    let chunks = chunk.get_u64();
+    let create_time = chunk.get_u64();
+    let update_time = chunk.get_u64();
+    let content_type = match get_str(chunk)? {
+        x if x.is_empty() => None,
+        x => Some(x)
+    };
+
+    // 读取自定义头
+    if chunk.len() < 4 {
+        return None;
+    }
+
+    let mut headers = HashMap::new();
+    for _ in 0..chunk.get_u32() {
+        let key = get_str(chunk)?;
+        let value = get_str(chunk)?;
+        headers.insert(key, value);
+    }
+
+    // 早期版本没有保存摘要
+    let mut digest = None;
+    if chunk.len() >= 33 && chunk.get_u8() == 1 {
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&chunk[..32]);
+        chunk.advance(32);
+        digest = Some(value);
+    }
+
+    // 早期版本没有保存摘要状态
+    let mut state = None;
+    if chunk.len() >= 33 && chunk.get_u8() == 1 {
+        let mut value = [0u32; 8];
+        for word in value.iter_mut() {
+            *word = chunk.get_u32();
+        }
+
+        state = Some(value);
+    }
+
+    Some(ObjectMeta {
+        size,
+        chunks,
+        create_time,
+        update_time,
+        content_type,
+        headers,
+        digest,
+        state,
+    })
+}
+
+/// 写入字符串
+///
+/// 字符串使用U32长度前缀
+fn put_str(packet: &mut BytesMut, value: &str) {
+    packet.put_u32(value.len() as u32);
+    packet.extend_from_slice(value.as_bytes());
+}
+
+/// 读取字符串
+#[rustfmt::skip]
+fn get_str(chunk: &mut &[u8]) -> Option<String> {
+    if chunk.len() < 4 {
+        return None;
+    }
+
+    let size = chunk.get_u32() as usize;
+    if size > chunk.len() {
+        return None;
+    }
+
+    let value = String::from_utf8_lossy(&chunk[..size]).to_string();
+    chunk.advance(size);
+    Some(value)
+}
@@ -0,0 +1,123 @@
+use super::{Kernel, ObjectMeta};
+use anyhow::{anyhow, Result};
+
+/// 单次列出的键数量
+const BATCH_SIZE: usize = 1000;
+
+/// 迁移报告
+///
+/// `objects` 已迁移的数据数量  
+/// `skipped` 目标中已经存在而跳过的数据数量  
+/// `bytes` 已迁移的字节数  
+/// `last_key` 最后处理的键
+#[derive(Debug, Clone, Default)]
+pub struct MigrateReport {
+    pub objects: u64,
+    pub skipped: u64,
+    pub bytes: u64,
+    pub last_key: Option<Vec<u8>>,
+}
+
+impl Kernel {
+    /// 迁移到新的存储
+    ///
+    /// 使用当前存储的轨道和分片格式读取所有数据，
+    /// 按照目标存储的格式重新写入，
+    /// 目标存储会生成新的分配表，
+    /// 元数据包括创建时间和更新时间保持不变.
+    ///
+    /// 目标中已经存在并且长度和摘要一致的数据将被跳过，
+    /// 所以迁移中断之后可以直接重新执行，
+    /// 每处理一个数据调用一次进度回调，
+    /// 迁移只读取当前存储，可以使用只读实例
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, KernelOptions, ChunkFormat};
+    ///
+    /// let mut source_options = KernelOptions::load(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// source_options.read_only = true;
+    /// let source = Kernel::with_options(source_options).unwrap();
+    ///
+    /// let options = KernelOptions::builder()
+    ///     .path("./.static.new")
+    ///     .chunk_format(ChunkFormat::Crc32c)
+    ///     .build()
+    ///     .unwrap();
+    ///
+    /// let target = Kernel::with_options(options).unwrap();
+    /// source.migrate(&target, |report| {
+    ///     println!("{} {}", report.objects, report.bytes);
+    /// }).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn migrate(
+        &self, 
+        target: &Kernel, 
+        mut progress: impl FnMut(&MigrateReport)
+    ) -> Result<MigrateReport> {
+        if self.superblock().uuid == target.superblock().uuid {
+            return Err(anyhow!("cannot migrate into the same store"));
+        }
+
+        let mut report = MigrateReport::default();
+
+        // 分批列出键
+        // 直到没有剩余的键
+    loop {
+        let keys = self.list(b"", report.last_key.as_deref(), BATCH_SIZE)?;
+        if keys.is_empty() {
+            break;
+        }
+
+        for key in keys {
+            let _source = self.read_lock(&key)?;
+            let _target = target.write_lock(&key)?;
+            
+            // 列出之后数据可能已经被删除
+            let alloc_map = match self.index.get(&key)? {
+                Some(x) => x,
+                None => continue
+            };
+
+            let meta = match self.index.get_meta(&key)? {
+                None => self.legacy_meta(alloc_map.clone())?,
+                Some(x) => x
+            };
+
+            if exists(target, &key, &meta)? {
+                report.skipped += 1;
+            } else {
+                let size = meta.size;
+                let previous = target.index.get(&key)?;
+                let object = self.disk.open(alloc_map)?;
+                target.store(&key, object, meta, previous)?;
+                report.bytes += size;
+                report.objects += 1;
+            }
+
+            report.last_key = Some(key);
+            progress(&report);
+        }
+    }
+
+        Ok(report)
+    }
+}
+
+/// 目标中是否已经存在相同的数据
+///
+/// 旧版本数据没有摘要，
+/// 这时候只比较长度
+#[rustfmt::skip]
+fn exists(target: &Kernel, key: &[u8], meta: &ObjectMeta) -> Result<bool> {
+    Ok(match target.index.get_meta(key)? {
+        Some(x) => x.size == meta.size && (meta.digest.is_none() || x.digest == meta.digest),
+        None => false
+    })
+}
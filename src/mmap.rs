@@ -0,0 +1,68 @@
+use anyhow::Result;
+use memmap2::Mmap;
+use std::io::{Error, ErrorKind};
+use std::fs::File;
+
+/// 文件映射
+///
+/// 将整个轨道文件映射到内存，
+/// 读取分片直接返回映射区域的切片，
+/// 轨道增长之后读取超出映射范围的时候重新映射，
+/// 映射是共享的，所以通过文件句柄写入的数据立即可见
+pub struct Map {
+    mmap: Option<Mmap>,
+}
+
+impl Map {
+    /// 创建映射
+    ///
+    /// 第一次读取的时候才会映射文件
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Map;
+    ///
+    /// let map = Map::new();
+    /// ```
+    pub fn new() -> Self {
+        Self {
+            mmap: None,
+        }
+    }
+
+    /// 读取映射区域
+    ///
+    /// 读取范围超出当前映射的时候按照文件当前长度重新映射，
+    /// 文件长度仍然不足的时候返回`UnexpectedEof`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Map;
+    /// use std::fs::File;
+    ///
+    /// let file = File::open("1.track").unwrap();
+    /// let mut map = Map::new();
+    /// let chunk = map.get(&file, 8192, 4096).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn get(&mut self, file: &File, offset: u64, len: usize) -> Result<&[u8]> {
+        let end = offset + len as u64;
+        let mapped = self.mmap.as_ref().map(|x| x.len() as u64).unwrap_or(0);
+        if end > mapped {
+            if file.metadata()?.len() < end {
+                return Err(Error::from(ErrorKind::UnexpectedEof).into());
+            }
+
+            // 映射区域在文件被截断之后访问会触发SIGBUS，
+            // 轨道文件只会增长，所以这里是安全的
+            self.mmap = Some(unsafe { Mmap::map(file)? });
+        }
+
+        match self.mmap.as_ref() {
+            Some(mmap) => Ok(&mmap[offset as usize..end as usize]),
+            None => Err(Error::from(ErrorKind::UnexpectedEof).into())
+        }
+    }
+}
@@ -0,0 +1,261 @@
+use super::{fs, track, Allocation, ChunkFormat, Durability, KernelOptions};
+use anyhow::{anyhow, Result};
+
+/// 索引配置
+///
+/// 未设置的项使用RocksDB的默认值
+///
+/// `cache_size` 块缓存长度  
+/// `block_size` 数据块长度  
+/// `write_buffer_size` 内存表长度  
+/// `max_open_files` 最大打开文件数量  
+/// `parallelism` 后台线程数量
+#[derive(Debug, Clone, Default)]
+pub struct IndexOptions {
+    pub cache_size: Option<usize>,
+    pub block_size: Option<usize>,
+    pub write_buffer_size: Option<usize>,
+    pub max_open_files: Option<i32>,
+    pub parallelism: Option<i32>,
+}
+
+/// 核心配置构建器
+///
+/// 默认轨道长度为1GB，分片长度为4KB，
+/// 默认不使用直接读写，分片缓存和内存映射，
+/// 存储目录必须设置
+pub struct KernelOptionsBuilder {
+    path: Option<String>,
+    track_size: u64,
+    chunk_size: u64,
+    chunk_format: ChunkFormat,
+    durability: Durability,
+    allocation: Allocation,
+    index: IndexOptions,
+    chunk_cache_size: u64,
+    direct_io: bool,
+    mmap: bool,
+    read_only: bool,
+}
+
+impl Default for KernelOptionsBuilder {
+    fn default() -> Self {
+        Self {
+            track_size: 1024 * 1024 * 1024,
+            chunk_format: ChunkFormat::Plain,
+            durability: Durability::None,
+            allocation: Allocation::Sequential,
+            index: IndexOptions::default(),
+            chunk_cache_size: 0,
+            direct_io: false,
+            mmap: false,
+            read_only: false,
+            chunk_size: 4096,
+            path: None,
+        }
+    }
+}
+
+impl KernelOptionsBuilder {
+    /// 存储目录
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    /// 轨道文件最大长度
+    pub fn track_size(mut self, size: u64) -> Self {
+        self.track_size = size;
+        self
+    }
+
+    /// 分片长度
+    pub fn chunk_size(mut self, size: u64) -> Self {
+        self.chunk_size = size;
+        self
+    }
+
+    /// 分片格式
+    pub fn chunk_format(mut self, format: ChunkFormat) -> Self {
+        self.chunk_format = format;
+        self
+    }
+
+    /// 持久化策略
+    pub fn durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    /// 轨道分配策略
+    pub fn allocation(mut self, allocation: Allocation) -> Self {
+        self.allocation = allocation;
+        self
+    }
+
+    /// 轨道文件是否绕过页缓存
+    ///
+    /// 使用`O_DIRECT`打开轨道文件，
+    /// 只支持Linux，分片长度必须是4KB的整数倍
+    pub fn direct_io(mut self, direct: bool) -> Self {
+        self.direct_io = direct;
+        self
+    }
+
+    /// 分片缓存长度
+    pub fn chunk_cache_size(mut self, size: u64) -> Self {
+        self.chunk_cache_size = size;
+        self
+    }
+
+    /// 是否使用内存映射读取轨道文件
+    ///
+    /// 读取分片不需要系统调用和复制，
+    /// 需要启用`memmap2`特性，不能和直接读写同时使用
+    pub fn mmap(mut self, mmap: bool) -> Self {
+        self.mmap = mmap;
+        self
+    }
+
+    /// 是否以只读方式打开
+    ///
+    /// 不写入超级块，不打开日志，也不恢复未完成的操作，
+    /// 所有修改操作都返回错误
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// 索引块缓存长度
+    pub fn index_cache_size(mut self, size: usize) -> Self {
+        self.index.cache_size = Some(size);
+        self
+    }
+
+    /// 索引数据块长度
+    pub fn index_block_size(mut self, size: usize) -> Self {
+        self.index.block_size = Some(size);
+        self
+    }
+
+    /// 索引内存表长度
+    pub fn index_write_buffer_size(mut self, size: usize) -> Self {
+        self.index.write_buffer_size = Some(size);
+        self
+    }
+
+    /// 索引最大打开文件数量
+    pub fn index_max_open_files(mut self, count: i32) -> Self {
+        self.index.max_open_files = Some(count);
+        self
+    }
+
+    /// 索引后台线程数量
+    pub fn index_parallelism(mut self, count: i32) -> Self {
+        self.index.parallelism = Some(count);
+        self
+    }
+
+    /// 构建配置
+    ///
+    /// 存储目录必须设置，
+    /// 其他检查见`KernelOptions::validate`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{KernelOptions, Durability};
+    ///
+    /// let options = KernelOptions::builder()
+    ///     .path("./.static")
+    ///     .chunk_size(8192)
+    ///     .track_size(1024 * 1024 * 1024)
+    ///     .durability(Durability::PerObject)
+    ///     .index_cache_size(64 * 1024 * 1024)
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn build(self) -> Result<KernelOptions> {
+        let path = match self.path {
+            None => return Err(anyhow!("path is required")),
+            Some(x) => x
+        };
+
+        let options = KernelOptions {
+            track_size: self.track_size,
+            chunk_size: self.chunk_size,
+            chunk_format: self.chunk_format,
+            durability: self.durability,
+            allocation: self.allocation,
+            index: self.index,
+            chunk_cache_size: self.chunk_cache_size,
+            direct_io: self.direct_io,
+            mmap: self.mmap,
+            read_only: self.read_only,
+            path,
+        };
+
+        options.validate()?;
+        Ok(options)
+    }
+}
+
+impl KernelOptions {
+    /// 检查配置
+    ///
+    /// 分片长度必须大于分片头长度，
+    /// 分片内部数据长度必须能够使用u16表示，
+    /// 轨道长度必须是分片长度的整数倍，
+    /// 并且除去轨道文件头之后至少可以容纳一个分片，
+    /// 直接读写的时候分片长度必须对齐，
+    /// 内存映射需要启用`memmap2`特性并且不能和直接读写同时使用，
+    /// 构建配置和创建实例的时候都会检查
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::KernelOptions;
+    ///
+    /// let mut options = KernelOptions::from(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// );
+    ///
+    /// options.chunk_size = 5000;
+    /// assert!(options.validate().is_err());
+    /// ```
+    #[rustfmt::skip]
+    pub fn validate(&self) -> Result<()> {
+        let header_size = self.chunk_format.header_size();
+        if self.chunk_size <= header_size {
+            return Err(anyhow!("chunk size must exceed {} bytes", header_size));
+        }
+
+        if self.chunk_size - header_size > u16::MAX as u64 {
+            return Err(anyhow!("chunk size too large: {}", self.chunk_size));
+        }
+
+        if self.track_size % self.chunk_size != 0 {
+            return Err(anyhow!("track size must be a multiple of chunk size"));
+        }
+
+        if self.track_size < track::HEADER_SIZE + self.chunk_size {
+            return Err(anyhow!("track size too small: {}", self.track_size));
+        }
+
+        if self.direct_io && self.chunk_size % fs::ALIGN as u64 != 0 {
+            return Err(anyhow!("chunk size must be a multiple of {} with direct io", fs::ALIGN));
+        }
+
+        if self.mmap && !cfg!(feature = "memmap2") {
+            return Err(anyhow!("mmap requires the memmap2 feature"));
+        }
+
+        if self.mmap && self.direct_io {
+            return Err(anyhow!("mmap can not be used with direct io"));
+        }
+
+        Ok(())
+    }
+}
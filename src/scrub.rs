@@ -0,0 +1,261 @@
+use super::{Kernel, ObjectMeta};
+use sha2::{Digest, Sha256};
+use anyhow::Result;
+use std::time::{
+    Duration, 
+    Instant
+};
+
+/// 巡检配置
+///
+/// `start_after` 从该键之后开始巡检  
+/// `limit` 单次最多巡检的数据数量  
+/// `rate` 每秒最多读取的字节数，每个数据巡检完成之后限速
+#[derive(Debug, Clone, Default)]
+pub struct ScrubOptions {
+    pub start_after: Option<Vec<u8>>,
+    pub limit: Option<usize>,
+    pub rate: Option<u64>,
+}
+
+/// 损坏数据
+///
+/// `key` 数据的键  
+/// `reason` 损坏原因
+#[derive(Debug, Clone, PartialEq)]
+pub struct Damage {
+    pub key: Vec<u8>,
+    pub reason: String,
+}
+
+/// 巡检报告
+///
+/// `objects` 已巡检的数据数量  
+/// `chunks` 已巡检的分片数量  
+/// `bytes` 已读取的字节数  
+/// `damaged` 损坏数据列表  
+/// `last_key` 最后巡检的键，用于继续巡检  
+/// `finished` 是否已经巡检全部数据
+#[derive(Debug, Clone, Default)]
+pub struct ScrubReport {
+    pub objects: u64,
+    pub chunks: u64,
+    pub bytes: u64,
+    pub damaged: Vec<Damage>,
+    pub last_key: Option<Vec<u8>>,
+    pub finished: bool,
+}
+
+/// 限速器
+///
+/// 根据已读取的字节数计算预期耗时，
+/// 如果实际耗时较短则休眠补齐
+struct Limiter {
+    rate: Option<u64>,
+    start: Instant,
+    bytes: u64,
+}
+
+impl Limiter {
+    fn new(rate: Option<u64>) -> Self {
+        Self {
+            start: Instant::now(),
+            bytes: 0,
+            rate,
+        }
+    }
+
+    #[rustfmt::skip]
+    fn consume(&mut self, size: u64) {
+        self.bytes += size;
+        let rate = match self.rate {
+            Some(x) if x > 0 => x,
+            _ => return
+        };
+
+        let expected = Duration::from_secs_f64(self.bytes as f64 / rate as f64);
+        let elapsed = self.start.elapsed();
+        if expected > elapsed {
+            std::thread::sleep(expected - elapsed);
+        }
+    }
+}
+
+impl Kernel {
+    /// 巡检全部数据
+    ///
+    /// 遍历所有索引项，
+    /// 读取并校验每个分片，
+    /// 检查链表和分配表是否一致
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Kernel;
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let report = kernel.scrub().unwrap();
+    /// for damage in report.damaged {
+    ///     println!("{:?} {}", damage.key, damage.reason);
+    /// }
+    /// ```
+    pub fn scrub(&self) -> Result<ScrubReport> {
+        self.scrub_with(&ScrubOptions::default())
+    }
+
+    /// 按配置巡检数据
+    ///
+    /// 可以限制单次巡检的数量和读取速度，
+    /// 使用报告中的`last_key`继续巡检
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Kernel, ScrubOptions};
+    ///
+    /// let kernel = Kernel::new(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// ).unwrap();
+    ///
+    /// let mut options = ScrubOptions {
+    ///     rate: Some(50 * 1024 * 1024),
+    ///     limit: Some(1000),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// loop {
+    ///     let report = kernel.scrub_with(&options).unwrap();
+    ///     if report.finished {
+    ///         break;
+    ///     }
+    ///
+    ///     options.start_after = report.last_key;
+    /// }
+    /// ```
+    #[rustfmt::skip]
+    pub fn scrub_with(&self, options: &ScrubOptions) -> Result<ScrubReport> {
+        let chunk_size = self.options.chunk_size;
+        let limit = options.limit.unwrap_or(usize::MAX);
+        let mut limiter = Limiter::new(options.rate);
+        let mut report = ScrubReport::default();
+        let mut scan = self.index.scan(
+            b"", 
+            options.start_after.as_deref()
+        );
+
+        // 遍历索引项
+        // 直到达到单次巡检的数量限制
+    loop {
+        if report.objects as usize >= limit {
+            break;
+        }
+
+        let (key, _) = match scan.next() {
+            Some(x) => x,
+            None => {
+                report.finished = true;
+                break;
+            }
+        };
+
+        // 遍历开始之后索引项可能已经被替换，
+        // 持有读锁之后重新读取索引项
+        let guard = self.read_lock(&key)?;
+        let alloc_map = match self.index.get(&key)? {
+            Some(x) => x,
+            None => continue
+        };
+
+        let meta = self.index.get_meta(&key)?;
+
+        // 展开分配表
+        // 用于检查分片链表
+        let offsets: Vec<u64> = alloc_map
+            .iter()
+            .flat_map(|(_, list)| list.iter().copied())
+            .collect();
+
+        let diff_size = self.options.diff_size() as usize;
+        let mut hasher = Sha256::new();
+        let mut reason = None;
+        let mut position = 0;
+        let mut size = 0;
+
+        let result = self.disk.walk(&alloc_map, |track, offset, next, data| {
+            report.bytes += chunk_size;
+            report.chunks += 1;
+            position += 1;
+
+            // 下个分片必须和分配表一致，
+            // 尾部分片之外的分片必须是满载的
+            let expected = offsets.get(position).copied();
+            if reason.is_none() && next != expected {
+                reason = Some(format!(
+                    "broken chain: track {} offset {} next {:?} expected {:?}", 
+                    track, offset, next, expected
+                ));
+            }
+
+            if reason.is_none() && expected.is_some() && data.len() != diff_size {
+                reason = Some(format!(
+                    "short chunk: track {} offset {} size {}", 
+                    track, offset, data.len()
+                ));
+            }
+
+            hasher.update(data);
+            size += data.len() as u64;
+        });
+
+        // 释放读锁之后再限速，
+        // 休眠的时候不阻塞这个键的写入以及轨道的读写
+        drop(guard);
+        limiter.consume(position as u64 * chunk_size);
+        if let Err(e) = result {
+            reason = Some(e.to_string());
+        }
+
+        if reason.is_none() {
+            reason = check_meta(meta.as_ref(), offsets.len() as u64, size, hasher);
+        }
+
+        if let Some(reason) = reason {
+            report.damaged.push(Damage {
+                key: key.to_vec(),
+                reason
+            });
+        }
+
+        report.last_key = Some(key.to_vec());
+        report.objects += 1;
+    }
+
+        Ok(report)
+    }
+}
+
+/// 检查元数据
+///
+/// 检查长度，分片数量以及数据摘要，
+/// 旧版本数据没有元数据的时候不做检查
+#[rustfmt::skip]
+fn check_meta(meta: Option<&ObjectMeta>, chunks: u64, size: u64, hasher: Sha256) -> Option<String> {
+    let meta = meta?;
+    if meta.chunks != chunks {
+        return Some(format!("chunk count mismatch: {} expected {}", chunks, meta.chunks));
+    }
+
+    if meta.size != size {
+        return Some(format!("size mismatch: {} expected {}", size, meta.size));
+    }
+
+    match meta.digest {
+        Some(digest) if digest[..] != hasher.finalize()[..] => Some("digest mismatch".to_string()),
+        _ => None
+    }
+}
@@ -0,0 +1,114 @@
+use anyhow::Result;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::io::{Error, ErrorKind};
+use std::fs::File;
+
+/// 等待可写超时时间(毫秒)
+///
+/// 目标长时间不可写的时候放弃发送，
+/// 避免一直占用调用线程
+const WRITABLE_TIMEOUT: i32 = 30 * 1000;
+
+/// 零拷贝发送
+///
+/// 将文件中指定范围的数据直接发送到目标描述符，
+/// 目标是管道的时候使用`splice`，
+/// 否则使用`sendfile`，数据不经过用户空间，
+/// 目标是非阻塞描述符的时候等待可写之后继续发送，
+/// 等待超时的时候返回`TimedOut`
+///
+/// # Examples
+///
+/// ```no_run
+/// use super::transfer;
+/// use std::net::TcpStream;
+/// use std::os::unix::io::AsRawFd;
+/// use std::fs::File;
+///
+/// let file = File::open("1.track").unwrap();
+/// let stream = TcpStream::connect("127.0.0.1:8080").unwrap();
+/// transfer(&file, 8202, 4086, stream.as_raw_fd()).unwrap();
+/// ```
+#[rustfmt::skip]
+pub fn transfer(file: &File, offset: u64, len: usize, fd: RawFd) -> Result<()> {
+    let pipe = is_pipe(fd)?;
+    let mut position = offset as libc::loff_t;
+    let mut remaining = len;
+while remaining > 0 {
+    let size = unsafe {
+        match pipe {
+            true => libc::splice(
+                file.as_raw_fd(),
+                &mut position,
+                fd,
+                std::ptr::null_mut(),
+                remaining,
+                libc::SPLICE_F_MOVE
+            ),
+            false => libc::sendfile(
+                fd,
+                file.as_raw_fd(),
+                &mut position,
+                remaining
+            )
+        }
+    };
+
+    // 被信号中断的时候重试，
+    // 目标暂时不可写的时候等待可写
+    if size < 0 {
+        let e = Error::last_os_error();
+        match e.kind() {
+            ErrorKind::Interrupted => continue,
+            ErrorKind::WouldBlock => wait_writable(fd)?,
+            _ => return Err(e.into())
+        }
+
+        continue;
+    }
+
+    // 文件长度不足
+    if size == 0 {
+        return Err(Error::from(ErrorKind::UnexpectedEof).into());
+    }
+
+    remaining -= size as usize;
+}
+
+    Ok(())
+}
+
+/// 检查描述符是否为管道
+fn is_pipe(fd: RawFd) -> Result<bool> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(Error::last_os_error().into());
+    }
+
+    Ok(stat.st_mode & libc::S_IFMT == libc::S_IFIFO)
+}
+
+/// 等待描述符可写
+///
+/// 被信号中断的时候直接返回，
+/// 调用方重新发送的时候会再次等待，
+/// 超时的时候返回`TimedOut`
+fn wait_writable(fd: RawFd) -> Result<()> {
+    let mut poll = libc::pollfd {
+        events: libc::POLLOUT,
+        revents: 0,
+        fd,
+    };
+
+    match unsafe { libc::poll(&mut poll, 1, WRITABLE_TIMEOUT) } {
+        0 => Err(Error::from(ErrorKind::TimedOut).into()),
+        x if x < 0 => {
+            let e = Error::last_os_error();
+            match e.kind() {
+                ErrorKind::Interrupted => Ok(()),
+                _ => Err(e.into())
+            }
+        },
+        _ => Ok(())
+    }
+}
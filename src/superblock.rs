@@ -0,0 +1,245 @@
+use super::{ChunkFormat, KernelOptions};
+use anyhow::{anyhow, Result};
+use std::path::Path;
+use uuid::Uuid;
+use bytes::{
+    Buf, 
+    BufMut, 
+    BytesMut
+};
+
+use std::fs::{
+    rename,
+    File
+};
+
+use std::io::{
+    ErrorKind,
+    Read,
+    Write
+};
+
+/// 超级块文件魔数
+const MAGIC: &[u8; 8] = b"PHYSETER";
+
+/// 当前存储格式版本
+pub const FORMAT_VERSION: u32 = 1;
+
+/// 超级块长度
+const SIZE: usize = 8 + 4 + 8 + 8 + 1 + 16 + 4;
+
+/// 超级块
+///
+/// 保存存储目录的格式参数，
+/// 在首次初始化存储目录的时候写入，
+/// 之后每次打开都检查配置是否和存储目录一致，
+/// 避免使用不同的分片长度或者轨道长度解析已有数据
+///
+/// `version` 存储格式版本  
+/// `chunk_size` 分片长度  
+/// `track_size` 轨道文件最大长度  
+/// `chunk_format` 分片格式  
+/// `uuid` 存储目录唯一ID
+///
+/// ```
+///
+///     +---------------------------------------------------------+
+///     | 8 bytes | U32 | U64 | U64 | U8 | 16 bytes | U32 (crc32c) |
+///     +---------------------------------------------------------+
+///         |        |     |     |     |     |-> uuid
+///         |        |     |     |     |-> chunk format
+///         |        |     |     |-> track size
+///         |        |     |-> chunk size
+///         |        |-> format version
+///         |-> magic
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Superblock {
+    pub version: u32,
+    pub chunk_size: u64,
+    pub track_size: u64,
+    pub chunk_format: ChunkFormat,
+    pub uuid: Uuid,
+}
+
+impl Superblock {
+    /// 根据配置创建超级块
+    ///
+    /// 每次创建都会生成新的存储目录ID
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Superblock, KernelOptions};
+    ///
+    /// let options = KernelOptions::from(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// );
+    ///
+    /// let superblock = Superblock::new(&options);
+    /// ```
+    pub fn new(options: &KernelOptions) -> Self {
+        Self {
+            version: FORMAT_VERSION,
+            chunk_size: options.chunk_size,
+            track_size: options.track_size,
+            chunk_format: options.chunk_format,
+            uuid: Uuid::new_v4(),
+        }
+    }
+
+    /// 读取超级块
+    ///
+    /// 超级块文件不存在的时候返回空
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Superblock;
+    ///
+    /// let superblock = Superblock::load("./.static").unwrap();
+    /// ```
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Self>> {
+        let mut file = match File::open(path.as_ref().join("superblock")) {
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+            Ok(x) => x,
+        };
+
+        let mut buffer = Vec::new();
+        file.read_to_end(&mut buffer)?;
+        decoder(&buffer).map(Some)
+    }
+
+    /// 写入超级块
+    ///
+    /// 先写入临时文件再替换，
+    /// 避免写入过程中崩溃导致超级块损坏
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Superblock, KernelOptions};
+    ///
+    /// let options = KernelOptions::from(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// );
+    ///
+    /// Superblock::new(&options).save("./.static").unwrap();
+    /// ```
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let temp = path.as_ref().join("superblock.tmp");
+        let mut file = File::create(&temp)?;
+        file.write_all(&encoder(self))?;
+        file.sync_all()?;
+        rename(temp, path.as_ref().join("superblock"))?;
+        Ok(())
+    }
+
+    /// 检查配置
+    ///
+    /// 配置和超级块不一致的时候返回错误
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Superblock, KernelOptions};
+    ///
+    /// let options = KernelOptions::from(
+    ///     "./.static".to_string(),
+    ///     1024 * 1024 * 1024 * 1
+    /// );
+    ///
+    /// if let Some(superblock) = Superblock::load("./.static").unwrap() {
+    ///     superblock.check(&options).unwrap();
+    /// }
+    /// ```
+    pub fn check(&self, options: &KernelOptions) -> Result<()> {
+        if self.version > FORMAT_VERSION {
+            return Err(anyhow!("unsupported format version: {}", self.version));
+        }
+
+        if self.chunk_size != options.chunk_size {
+            return Err(anyhow!(
+                "chunk size mismatch: store {} options {}", 
+                self.chunk_size, 
+                options.chunk_size
+            ));
+        }
+
+        if self.track_size != options.track_size {
+            return Err(anyhow!(
+                "track size mismatch: store {} options {}", 
+                self.track_size, 
+                options.track_size
+            ));
+        }
+
+        if self.chunk_format != options.chunk_format {
+            return Err(anyhow!(
+                "chunk format mismatch: store {:?} options {:?}", 
+                self.chunk_format, 
+                options.chunk_format
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// 编码超级块
+fn encoder(superblock: &Superblock) -> BytesMut {
+    let mut packet = BytesMut::with_capacity(SIZE);
+    packet.extend_from_slice(MAGIC);
+    packet.put_u32(superblock.version);
+    packet.put_u64(superblock.chunk_size);
+    packet.put_u64(superblock.track_size);
+    packet.put_u8(match superblock.chunk_format {
+        ChunkFormat::Plain => 0,
+        ChunkFormat::Crc32c => 1,
+    });
+
+    packet.extend_from_slice(superblock.uuid.as_bytes());
+    let crc = crc32c::crc32c(&packet);
+    packet.put_u32(crc);
+    packet
+}
+
+/// 解码超级块
+#[rustfmt::skip]
+fn decoder(mut chunk: &[u8]) -> Result<Superblock> {
+    if chunk.len() < SIZE || &chunk[..8] != MAGIC {
+        return Err(anyhow!("invalid superblock"));
+    }
+
+    let crc = u32::from_be_bytes([
+        chunk[SIZE - 4], 
+        chunk[SIZE - 3], 
+        chunk[SIZE - 2], 
+        chunk[SIZE - 1]
+    ]);
+
+    if crc32c::crc32c(&chunk[..SIZE - 4]) != crc {
+        return Err(anyhow!("superblock checksum mismatch"));
+    }
+
+    chunk.advance(8);
+    let version = chunk.get_u32();
+    let chunk_size = chunk.get_u64();
+    let track_size = chunk.get_u64();
+    let chunk_format = match chunk.get_u8() {
+        0 => ChunkFormat::Plain,
+        1 => ChunkFormat::Crc32c,
+        x => return Err(anyhow!("unknown chunk format: {}", x))
+    };
+
+    Ok(Superblock {
+        uuid: Uuid::from_slice(&chunk[..16])?,
+        version,
+        chunk_size,
+        track_size,
+        chunk_format,
+    })
+}
@@ -1,6 +1,10 @@
-use anyhow::Result;
+use anyhow::{anyhow, Result};
+use std::collections::HashSet;
 use std::path::Path;
-use std::rc::Rc;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(target_os = "linux")]
+use std::fs::File;
 use bytes::{
     Buf, 
     BufMut, 
@@ -9,53 +13,133 @@ use bytes::{
 };
 
 use super::{
-    fs::Fs,
-    chunk::Codec,
+    fs::{Fs, AlignedBuffer},
+    chunk::{ChunkFormat, Codec, Corruption},
+    cache::Cache,
     KernelOptions
 };
 
+/// 旧版本轨道文件头长度
+const LEGACY_HEADER_SIZE: u64 = 24;
+
+/// 轨道文件头槽长度
+///
+/// 文件头有两个槽交替写入，
+/// 所以文件头总长度为两个槽
+const SLOT_SIZE: u64 = 4096;
+
+/// 轨道文件头长度
+pub const HEADER_SIZE: u64 = SLOT_SIZE * 2;
+
+/// 轨道文件头魔数
+const MAGIC: &[u8; 8] = b"PHYTRACK";
+
+/// 轨道文件头版本
+const VERSION: u32 = 1;
+
+/// 轨道文件头槽内有效长度
+const SLOT_DATA_SIZE: usize = 8 + 4 + 2 + 8 + 8 + 8 + 8 + 8 + 4;
+
+/// 轨道文件头
+///
+/// `seq` 写入序号，读取时使用序号最大的有效槽  
+/// `free_start` 失效分片链表头部  
+/// `free_end` 失效分片链表尾部  
+/// `size` 数据长度
+///
+/// ```
+///
+///     +------------------------------------------------------------+
+///     | 8 bytes | U32 | U16 | U64 | U64 | U64 | U64 | U64 | U32    |
+///     +------------------------------------------------------------+
+///         |        |     |     |     |     |     |     |     |-> crc32c
+///         |        |     |     |     |     |     |     |-> data size
+///         |        |     |     |     |     |     |-> free chunk list last offset
+///         |        |     |     |     |     |-> free chunk list first offset
+///         |        |     |     |     |-> seq
+///         |        |     |     |-> chunk size
+///         |        |     |-> track id
+///         |        |-> version
+///         |-> magic
+/// ```
+struct Header {
+    id: u16,
+    chunk_size: u64,
+    seq: u64,
+    free_start: u64,
+    free_end: u64,
+    size: u64,
+}
+
 /// 存储轨道
 ///
 /// 数据存储在轨道文件内，
 /// 数据被拆分成固定大小的分片以链表形式写入，
-/// 删除数据只会标记分片为失效，下次写入将覆盖分片
+/// 删除数据只会标记分片为失效，下次写入将覆盖分片，
+/// 读取缓冲区是对齐的，可以直接用于直接读写
 pub struct Track {
-    options: Rc<KernelOptions>,
-    buffer: Vec<u8>,
+    options: Arc<KernelOptions>,
+    cache: Option<Arc<Cache>>,
+    buffer: AlignedBuffer,
     free_start: u64,
+    header_size: u64,
+    version: u32,
+    seq: u64,
+    id: u16,
     real_size: u64,
     free_end: u64,
+    freed: u64,
     chunk: Codec,
     size: u64,
     file: Fs,
+    remaining: Arc<AtomicU64>,
 }
 
 impl Track {
     /// 创建轨道
     ///
+    /// 设置分片缓存的时候读取优先使用缓存，
+    /// 只读实例以只读方式打开轨道文件
+    ///
     /// ```no_run
     /// use super::{Track, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let track = Track::new(0, options).unwrap();
+    /// let track = Track::new(0, options, None).unwrap();
     /// ```
-    pub fn new(id: u16, options: Rc<KernelOptions>) -> Result<Track> {
+    pub fn new(
+        id: u16, 
+        options: Arc<KernelOptions>, 
+        cache: Option<Arc<Cache>>
+    ) -> Result<Track> {
         let path: &Path = options.path.as_ref();
         let track_path = path.join(format!("{}.track", id));
+        let file = match options.read_only {
+            true => Fs::read_only(track_path, options.direct_io)?,
+            false => Fs::new(track_path, options.direct_io)?
+        };
+
         Ok(Self {
-            buffer: vec![0u8; options.chunk_size as usize],
+            buffer: AlignedBuffer::new(options.chunk_size as usize),
             chunk: Codec::new(options.clone()),
-            file: Fs::new(track_path)?,
+            file,
             free_start: 0,
+            header_size: 0,
             real_size: 0,
+            version: 0,
+            seq: 0,
+            id,
             free_end: 0,
+            freed: 0,
             size: 0,
+            remaining: Arc::default(),
             options,
+            cache,
         })
     }
 
@@ -68,64 +152,259 @@ impl Track {
     ///
     /// ```no_run
     /// use super::{Track, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     /// ```
     pub fn init(&mut self) -> Result<()> {
         self.real_size = self.file.stat()?.len();
-        self.read_header()
+        self.read_header()?;
+
+        // 失效块的数量没有保存在文件头中，
+        // 打开的时候只知道是否存在失效块
+        self.freed = if self.free_start > 0 { 1 } else { 0 };
+        self.publish();
+        Ok(())
     }
 
     /// 读取分片数据
     ///
-    /// 读取单个分片数据
+    /// 读取单个分片数据，
+    /// 使用内存映射的时候直接返回映射中的分片，
+    /// 分片校验失败的时候返回`Corruption`
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::{Track, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     /// 
     /// let chunk = track.read(10).unwrap();
     /// ```
+    #[rustfmt::skip]
     pub fn read(&mut self, offset: u64) -> Result<(Option<u64>, &[u8])> {
-        self.file.intact_read(&mut self.buffer, offset)?;
+        #[cfg(feature = "memmap2")]
+        if self.options.mmap {
+            let chunk = self.file.mapped_read(offset, self.options.chunk_size as usize)?;
+            if !self.chunk.verify(chunk) {
+                return Err(Corruption { track: self.id, offset }.into());
+            }
+
+            return Ok(self.chunk.decoder(chunk));
+        }
+
+        let cached = self.cache.as_ref().and_then(|x| x.get(self.id, offset));
+        match cached {
+            Some(chunk) => self.buffer.copy_from_slice(&chunk),
+            None => {
+                self.file.intact_read(&mut self.buffer, offset)?;
+                if !self.chunk.verify(&self.buffer) {
+                    return Err(Corruption { track: self.id, offset }.into());
+                }
+
+                if let Some(cache) = self.cache.as_ref() {
+                    cache.insert(self.id, offset, &self.buffer);
+                }
+            }
+        }
+
         Ok(self.chunk.decoder(&self.buffer[..]))
     }
 
+    /// 批量读取分片数据
+    ///
+    /// 一次提交多个分片的读取，
+    /// 物理位置连续的分片合并为一次读取，
+    /// 已经缓存的分片不会重复读取，
+    /// 使用内存映射的时候直接从映射中复制，
+    /// 分片内容按照位置顺序追加到`output`，
+    /// 返回每个分片内容的长度，
+    /// 任何分片校验失败的时候返回`Corruption`
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    /// 
+    /// let mut output = Vec::new();
+    /// let sizes = track.read_batch(&[8192, 12288], &mut output).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn read_batch(&mut self, offsets: &[u64], output: &mut Vec<u8>) -> Result<Vec<usize>> {
+        let chunk_size = self.options.chunk_size as usize;
+        let mut sizes = Vec::with_capacity(offsets.len());
+
+        #[cfg(feature = "memmap2")]
+        if self.options.mmap {
+            for offset in offsets.iter().copied() {
+                let chunk = self.file.mapped_read(offset, chunk_size)?;
+                if !self.chunk.verify(chunk) {
+                    return Err(Corruption { track: self.id, offset }.into());
+                }
+
+                let data = self.chunk.decoder(chunk).1;
+                output.extend_from_slice(data);
+                sizes.push(data.len());
+            }
+
+            return Ok(sizes);
+        }
+
+        let cached: Vec<Option<Arc<Vec<u8>>>> = offsets
+            .iter()
+            .map(|offset| self.cache.as_ref().and_then(|x| x.get(self.id, *offset)))
+            .collect();
+
+        // 只读取没有缓存的分片，
+        // 连续的分片在缓冲区中也是连续的
+        let missing: Vec<u64> = offsets
+            .iter()
+            .zip(cached.iter())
+            .filter(|(_, chunk)| chunk.is_none())
+            .map(|(offset, _)| *offset)
+            .collect();
+        let mut buffer = AlignedBuffer::new(chunk_size * missing.len());
+        let mut chunks: Vec<(u64, &mut [u8])> = Vec::new();
+        let mut rest = &mut buffer[..];
+        for (start, count) in runs(&missing, chunk_size as u64) {
+            let (run, tail) = rest.split_at_mut(count * chunk_size);
+            chunks.push((start, run));
+            rest = tail;
+        }
+
+        self.file.batch_read(&mut chunks)?;
+
+        let mut raw = buffer.chunks(chunk_size);
+        for (offset, chunk) in offsets.iter().copied().zip(cached) {
+            let data = match chunk.as_ref() {
+                Some(chunk) => self.chunk.decoder(chunk).1,
+                None => {
+                    let chunk = raw.next().unwrap();
+                    if !self.chunk.verify(chunk) {
+                        return Err(Corruption { track: self.id, offset }.into());
+                    }
+
+                    if let Some(cache) = self.cache.as_ref() {
+                        cache.insert(self.id, offset, chunk);
+                    }
+
+                    self.chunk.decoder(chunk).1
+                }
+            };
+
+            output.extend_from_slice(data);
+            sizes.push(data.len());
+        }
+
+        Ok(sizes)
+    }
+
+    /// 预读分片
+    ///
+    /// 通知操作系统即将读取这些分片，
+    /// 连续的分片合并为一个范围，
+    /// 操作系统在后台将数据读入页缓存，
+    /// 直接读写的时候没有页缓存，所以不做处理
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    /// track.prefetch(&[8192, 12288]).unwrap();
+    /// ```
+    pub fn prefetch(&self, offsets: &[u64]) -> Result<()> {
+        if self.options.direct_io {
+            return Ok(());
+        }
+
+        let chunk_size = self.options.chunk_size;
+        for (start, count) in runs(offsets, chunk_size) {
+            self.file.advise(start, count as u64 * chunk_size)?;
+        }
+
+        Ok(())
+    }
+
+    /// 复制轨道文件句柄
+    ///
+    /// 零拷贝发送的时候只在持有轨道锁的时候复制句柄，
+    /// 发送过程不持有轨道锁，
+    /// 分片数据位于分片头之后，
+    /// 只支持Linux
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    /// 
+    /// let file = track.try_clone().unwrap();
+    /// ```
+    #[cfg(target_os = "linux")]
+    pub fn try_clone(&self) -> Result<File> {
+        self.file.try_clone()
+    }
+
     /// 分配分片写入位置
     ///
     /// 因为链表的特殊性，
     /// 所以这个地方并不直接写入数据，
-    /// 而是预先分配位置
+    /// 而是预先分配位置，
+    /// 从尾部分配的时候轨道文件增长，
+    /// 内存映射在读取超出映射范围的时候重新映射
     ///
     /// # Examples
     ///
     /// ```no_run
     /// use super::{Track, KernelOptions};
-    //// use std::rc::Rc;
+    //// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     ///
     /// let index = track.alloc().unwrap();
@@ -141,6 +420,7 @@ impl Track {
         if real_size + chunk_size <= track_size {
             self.real_size += chunk_size;
             self.size += chunk_size;
+            self.publish();
             return Ok(Some(real_size));
         }
 
@@ -153,19 +433,22 @@ impl Track {
         // 读取失效分片
         // 并解码失效分片
         let mut buffer = [0u8; 8];
-        self.file.read(&mut buffer, free_start)?;
+        self.file.intact_read(&mut buffer, free_start)?;
         let next = u64::from_be_bytes(buffer);
 
         // 检查失效分片是否已经分配完成
         // 如果分配完整则重置失效分片状态
-        Ok(if self.free_end > 0 && next == self.free_end {
+        if free_start == self.free_end {
             self.free_start = 0;
             self.free_end = 0;
-            None
+            self.freed = 0;
         } else {
             self.free_start = next;
-            Some(free_start)
-        })
+            self.freed = std::cmp::max(self.freed.saturating_sub(1), 1);
+        }
+
+        self.publish();
+        Ok(Some(free_start))
     }
 
     /// 删除数据
@@ -181,14 +464,14 @@ impl Track {
     ///
     /// ```no_run
     /// use super::{Track, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     ///
     /// let track_id = track.remove(10).unwrap();
@@ -199,27 +482,79 @@ impl Track {
         
         // 获取头部索引
         // 获取尾部索引
-        let first = alloc_map.first().unwrap();
-        let last = alloc_map.last().unwrap();
-        
-        // 失效索引尾部更新
-        // 更新为当前尾部位置
-        self.free_end = *last;
+        let first = *alloc_map.first().unwrap();
+        let last = *alloc_map.last().unwrap();
         
         // 如果当前没有已失效的块
         // 则直接更新头部索引
-        // 如果存在则首尾链接
-        if self.free_start > 0 {
-            let next_buf = first.to_be_bytes();
-            self.file.write(&next_buf, self.free_end)?;
+        // 如果存在则将原尾部链接到头部
+        if self.free_end > 0 {
+            self.file.write(&first.to_be_bytes(), self.free_end)?;
         } else {
-            self.free_start = *first;
+            self.free_start = first;
         }
         
+        // 尾部分片的下个分片可能指向其他轨道，
+        // 所以这里需要截断链表
+        self.file.write(&0u64.to_be_bytes(), last)?;
+        self.freed += alloc_map.len() as u64;
+        self.free_end = last;
+        self.invalidate(alloc_map);
+        self.publish();
+        
         // 保存状态
         self.flush()
     }
 
+    /// 释放分片
+    ///
+    /// 和`remove`不同，
+    /// 分片之间的链表可能没有写入，
+    /// 所以这里依次写入每个分片的下个分片位置，
+    /// 再链接到失效链表的尾部，
+    /// 同时将文件头中的长度修正为实际长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    ///
+    /// track.release(&[8192, 16384]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn release(&mut self, offsets: &[u64]) -> Result<()> {
+        for (i, offset) in offsets.iter().enumerate() {
+            let next = offsets.get(i + 1).copied().unwrap_or(0);
+            self.file.write(&next.to_be_bytes(), *offset)?;
+        }
+
+        if let (Some(first), Some(last)) = (offsets.first(), offsets.last()) {
+            if self.free_end > 0 {
+                self.file.write(&first.to_be_bytes(), self.free_end)?;
+            } else {
+                self.free_start = *first;
+            }
+
+            self.freed += offsets.len() as u64;
+            self.free_end = *last;
+            self.invalidate(offsets);
+        }
+
+        self.size = self.slots().last().map(|x| x + self.options.chunk_size).unwrap_or(self.header_size);
+        self.real_size = self.size;
+        self.publish();
+        self.flush()
+    }
+
     /// 写入分片
     ///
     /// 写入单个分片数据到磁盘文件
@@ -228,27 +563,74 @@ impl Track {
     ///
     /// ```no_run
     /// use super::{Track, Chunk, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
     /// let chunk = Chunk {
     ///     next: Some(17),
     ///     data: Bytes::from_static(b"hello"),
     /// };
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     ///
     /// track.write(&chunk, 20).unwrap();
     /// ```
     pub fn write(&mut self, next: Option<u64>, chunk: &[u8], index: u64) -> Result<()> {
+        self.invalidate(&[index]);
         self.file.write(&self.chunk.encoder(next, chunk), index)
     }
 
+    /// 批量写入分片
+    ///
+    /// 每个分片由写入位置，下个分片位置以及数据组成，
+    /// 所有分片编码到同一个对齐缓冲区，
+    /// 物理位置连续的分片合并为一次写入，
+    /// 所有写入一次提交
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"), 
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    ///
+    /// track.write_batch(&[(8192, Some(12288), &b"hello"[..]), (12288, None, &b"world"[..])]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn write_batch(&mut self, chunks: &[(u64, Option<u64>, &[u8])]) -> Result<()> {
+        let chunk_size = self.options.chunk_size as usize;
+        let mut buffer = AlignedBuffer::new(chunk_size * chunks.len());
+        for ((_, next, data), packet) in chunks.iter().zip(buffer.chunks_mut(chunk_size)) {
+            packet.copy_from_slice(&self.chunk.encoder(*next, data));
+        }
+
+        // 分片按照顺序编码，
+        // 连续的分片在缓冲区中也是连续的
+        let offsets: Vec<u64> = chunks.iter().map(|(index, _, _)| *index).collect();
+        let mut batch: Vec<(u64, &[u8])> = Vec::new();
+        let mut rest = &buffer[..];
+        for (start, count) in runs(&offsets, chunk_size as u64) {
+            let (run, tail) = rest.split_at(count * chunk_size);
+            batch.push((start, run));
+            rest = tail;
+        }
+
+        self.invalidate(&offsets);
+        self.file.batch_write(&batch)
+    }
+
     /// 写入结束
     ///
     /// 当数据流写入完成的时候，
@@ -261,53 +643,360 @@ impl Track {
     ///
     /// ```no_run
     /// use super::{Track, Chunk, KernelOptions};
-    /// use std::rc::Rc;
+    /// use std::sync::Arc;
     ///
     /// let chunk = Chunk {
     ///     next: Some(17),
     ///     data: Bytes::from_static(b"hello"),
     /// };
     ///
-    /// let options = Rc::new(KernelOptions::from(
+    /// let options = Arc::new(KernelOptions::from(
     ///     Path::new("./.static"), 
     ///     1024 * 1024 * 1024 * 1
     /// ));
     ///
-    /// let mut track = Track::new(0, options).unwrap();
+    /// let mut track = Track::new(0, options, None).unwrap();
     /// track.init().unwrap();
     ///
     /// track.write(Chunk, 20).unwrap();
     /// track.flush().unwrap();
     /// ```
     pub fn flush(&mut self) -> Result<()> {
-        let mut packet = BytesMut::new();
-        packet.put_u64(self.free_start);
-        packet.put_u64(self.free_end);
-        packet.put_u64(self.size);
-        self.file.write(&packet, 0)?;
+        if self.version == 0 {
+            let mut packet = BytesMut::new();
+            packet.put_u64(self.free_start);
+            packet.put_u64(self.free_end);
+            packet.put_u64(self.size);
+            self.file.write(&packet, 0)?;
+            return self.file.flush();
+        }
+
+        // 两个槽交替写入，
+        // 写入中途崩溃的时候另一个槽仍然有效
+        self.seq += 1;
+        self.write_slot(self.seq)?;
         self.file.flush()
     }
 
+    /// 轨道文件头版本
+    ///
+    /// 旧版本轨道文件头没有版本，
+    /// 这时候版本为0
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
+    /// 同步轨道
+    ///
+    /// 写入文件头之后将轨道文件推入磁盘
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    /// track.sync().unwrap();
+    /// ```
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.file.sync()
+    }
+
+    /// 轨道ID
+    pub fn id(&self) -> u16 {
+        self.id
+    }
+
+    /// 轨道文件头中记录的长度
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// 轨道文件的实际长度
+    pub fn real_size(&self) -> u64 {
+        self.real_size
+    }
+
+    /// 检查轨道布局
+    ///
+    /// 检查轨道文件是否符合当前配置，
+    /// 文件长度不能超过轨道长度，
+    /// 文件头中的长度和失效链表位置必须按照分片对齐，
+    /// 分片长度不一致的时候通常无法对齐，
+    /// 旧版本轨道只支持不带校验码的分片格式
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    /// track.check_layout().unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn check_layout(&self) -> Result<()> {
+        let chunk_size = self.options.chunk_size;
+        if self.real_size > self.options.track_size {
+            return Err(anyhow!(
+                "track {} size {} exceeds track size {}", 
+                self.id, 
+                self.real_size, 
+                self.options.track_size
+            ));
+        }
+
+        if self.version == 0 && self.options.chunk_format != ChunkFormat::Plain {
+            return Err(anyhow!("track {} legacy header requires plain chunks", self.id));
+        }
+
+        // 长度指向最后一个分片的尾部，
+        // 失效链表为空的时候位置为0
+        let aligned = |x: u64| x >= self.header_size && (x - self.header_size) % chunk_size == 0;
+        if !aligned(self.size) {
+            return Err(anyhow!("track {} size {} does not match chunk size", self.id, self.size));
+        }
+
+        for offset in [self.free_start, self.free_end] {
+            if offset != 0 && !aligned(offset) {
+                return Err(anyhow!("track {} free list does not match chunk size", self.id));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 剩余可以分配的分片数量
+    ///
+    /// 包含轨道尾部未使用的空间和失效块，
+    /// 打开之前已经存在的失效块只计为一个，
+    /// 所以这是一个估计值，
+    /// 只有返回0的时候才表示轨道已满
+    pub fn available(&self) -> u64 {
+        let chunk_size = self.options.chunk_size;
+        let track_size = self.options.track_size;
+        let tail = track_size.saturating_sub(self.real_size) / chunk_size;
+        tail + self.freed
+    }
+
+    /// 剩余可以分配的分片数量
+    ///
+    /// 和`available`相同，
+    /// 返回的计数在轨道状态变化的时候更新，
+    /// 读取计数不需要持有轨道锁
+    pub fn remaining(&self) -> Arc<AtomicU64> {
+        self.remaining.clone()
+    }
+
+    /// 遍历所有分片位置
+    ///
+    /// 返回轨道文件中所有完整分片的位置，
+    /// 不区分分片是否已经失效
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    ///
+    /// let count = track.slots().count();
+    /// ```
+    pub fn slots(&self) -> impl Iterator<Item = u64> {
+        let chunk_size = self.options.chunk_size;
+        let end = self.real_size;
+        (self.header_size..end)
+            .step_by(chunk_size as usize)
+            .take_while(move |x| x + chunk_size <= end)
+    }
+
+    /// 遍历失效分片链表
+    ///
+    /// 从链表头部开始直到链表尾部，
+    /// 如果链表损坏则返回已经遍历的分片以及损坏原因
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    ///
+    /// let (free, error) = track.free_list().unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn free_list(&mut self) -> Result<(Vec<u64>, Option<String>)> {
+        let chunk_size = self.options.chunk_size;
+        let mut visited = HashSet::new();
+        let mut result = Vec::new();
+        let mut offset = self.free_start;
+        let mut buffer = [0u8; 8];
+
+        // 链表为空
+    if offset == 0 {
+        return Ok((result, match self.free_end {
+            0 => None,
+            x => Some(format!("free list end {} without start", x))
+        }));
+    }
+
+    loop {
+        
+        // 分片必须位于轨道内并且对齐，
+        // 同时不能重复出现
+        if offset < self.header_size
+            || offset + chunk_size > self.real_size
+            || (offset - self.header_size) % chunk_size != 0 {
+            return Ok((result, Some(format!("invalid free chunk {}", offset))));
+        }
+
+        if !visited.insert(offset) {
+            return Ok((result, Some(format!("free list cycle at {}", offset))));
+        }
+
+        result.push(offset);
+        if offset == self.free_end {
+            return Ok((result, None));
+        }
+
+        self.file.intact_read(&mut buffer, offset)?;
+        offset = u64::from_be_bytes(buffer);
+        if offset == 0 {
+            return Ok((result, Some(format!("free list ends before {}", self.free_end))));
+        }
+    }
+    }
+
+    /// 重建失效分片链表
+    ///
+    /// 将给定的分片依次链接为新的失效分片链表，
+    /// 并将文件头中的长度修正为实际长度
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::{Track, KernelOptions};
+    /// use std::sync::Arc;
+    ///
+    /// let options = Arc::new(KernelOptions::from(
+    ///     Path::new("./.static"),
+    ///     1024 * 1024 * 1024 * 1
+    /// ));
+    ///
+    /// let mut track = Track::new(0, options, None).unwrap();
+    /// track.init().unwrap();
+    ///
+    /// track.rebuild(&[24, 4120]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn rebuild(&mut self, free: &[u64]) -> Result<()> {
+        for (i, offset) in free.iter().enumerate() {
+            let next = free.get(i + 1).copied().unwrap_or(0);
+            self.file.write(&next.to_be_bytes(), *offset)?;
+        }
+
+        self.invalidate(free);
+        self.free_start = free.first().copied().unwrap_or(0);
+        self.free_end = free.last().copied().unwrap_or(0);
+        self.freed = free.len() as u64;
+        self.size = self.slots().last().map(|x| x + self.options.chunk_size).unwrap_or(self.header_size);
+        self.real_size = self.size;
+        self.publish();
+        self.flush()
+    }
+
+    /// 更新剩余分片数量
+    fn publish(&self) {
+        self.remaining.store(self.available(), Ordering::Relaxed);
+    }
+
+    /// 删除缓存
+    ///
+    /// 分片被重写或者释放之后缓存不再有效
+    fn invalidate(&self, offsets: &[u64]) {
+        if let Some(cache) = self.cache.as_ref() {
+            for offset in offsets {
+                cache.remove(self.id, *offset);
+            }
+        }
+    }
+
     /// 创建默认文件头
     ///
-    /// 将默认的失效块头索引和尾部索引写入到磁盘文件,
+    /// 将两个槽都写入初始状态，
     /// 并初始化文件长度状态
     fn default_header(&mut self) -> Result<()> {
-        let mut buf = BytesMut::new();
-        buf.put_u64(0);
-        buf.put_u64(0);
-        buf.put_u64(24);
-        self.file.write(&buf, 0)?;
-        self.real_size = 24;
-        self.size = 24;
+        if HEADER_SIZE + self.options.chunk_size > self.options.track_size {
+            return Err(anyhow!("track size too small: {}", self.options.track_size));
+        }
+
+        self.header_size = HEADER_SIZE;
+        self.real_size = self.header_size;
+        self.size = self.header_size;
+        self.version = VERSION;
+        self.free_start = 0;
+        self.free_end = 0;
+        self.write_slot(0)?;
+        self.write_slot(1)?;
+        self.seq = 1;
         Ok(())
     }
 
+    /// 写入文件头槽
+    ///
+    /// 序号决定写入哪个槽
+    fn write_slot(&mut self, seq: u64) -> Result<()> {
+        let mut packet = encode_header(&Header {
+            chunk_size: self.options.chunk_size,
+            free_start: self.free_start,
+            free_end: self.free_end,
+            size: self.size,
+            id: self.id,
+            seq,
+        });
+
+        packet.resize(SLOT_SIZE as usize, 0);
+        self.file.write(&packet, (seq % 2) * SLOT_SIZE)
+    }
+
     /// 读取文件头
     ///
     /// 从磁盘文件中读取失效块头索引和尾部索引，
     /// 这是必要的操作，轨道实例化的时候必须要
-    /// 从文件中恢复上次的状态
+    /// 从文件中恢复上次的状态.
+    ///
+    /// 读取两个槽并使用序号最大的有效槽，
+    /// 两个槽都没有魔数并且文件长度符合旧版本轨道的时候
+    /// 按照旧版本文件头读取
+    #[rustfmt::skip]
     fn read_header(&mut self) -> Result<()> {
         // 如果文件为空
         // 则直接写入默认头索引
@@ -315,16 +1004,148 @@ impl Track {
             return self.default_header();
         }
 
-        // 从文件中读取头部
-        let mut buffer = [0u8; 24];
+        if self.real_size < LEGACY_HEADER_SIZE {
+            return Err(anyhow!("track {} truncated header", self.id));
+        }
+
+        // 读取两个槽，
+        // 跳过不完整或者校验失败的槽，
+        // 第一个槽写入中途崩溃的时候魔数可能已经损坏，
+        // 所以需要同时检查两个槽的魔数
+        let mut slots = Vec::with_capacity(2);
+        let mut magic = false;
+        let mut buffer = [0u8; SLOT_DATA_SIZE];
+        for index in 0..2 {
+            let offset = index * SLOT_SIZE;
+            if offset + SLOT_DATA_SIZE as u64 > self.real_size {
+                continue;
+            }
+
+            self.file.intact_read(&mut buffer, offset)?;
+            magic |= &buffer[..8] == MAGIC;
+            if let Some(header) = decode_header(&buffer) {
+                slots.push(header);
+            }
+        }
+
+        // 两个槽都没有魔数，
+        // 并且文件长度符合旧版本轨道的时候按照旧版本文件头读取，
+        // 旧版本文件头之后的分片没有对齐，
+        // 不能使用直接读写
+        let legacy = (self.real_size - LEGACY_HEADER_SIZE) % self.options.chunk_size == 0;
+        if !magic && legacy {
+            if self.options.direct_io {
+                return Err(anyhow!("track {} legacy header does not support direct io", self.id));
+            }
+
+            return self.read_legacy_header();
+        }
+
+        let header = match slots.into_iter().max_by_key(|x| x.seq) {
+            None => return Err(anyhow!("track {} invalid header", self.id)),
+            Some(x) => x
+        };
+
+        if header.id != self.id {
+            return Err(anyhow!("track {} header belongs to track {}", self.id, header.id));
+        }
+
+        if header.chunk_size != self.options.chunk_size {
+            return Err(anyhow!(
+                "track {} chunk size mismatch: track {} options {}", 
+                self.id, 
+                header.chunk_size, 
+                self.options.chunk_size
+            ));
+        }
+
+        self.header_size = HEADER_SIZE;
+        self.version = VERSION;
+        self.free_start = header.free_start;
+        self.free_end = header.free_end;
+        self.size = header.size;
+        self.seq = header.seq;
+        Ok(())
+    }
+
+    /// 读取旧版本文件头
+    ///
+    /// 旧版本文件头只有失效块头索引，
+    /// 尾部索引以及数据长度
+    fn read_legacy_header(&mut self) -> Result<()> {
+        let mut buffer = [0u8; LEGACY_HEADER_SIZE as usize];
         self.file.read(&mut buffer, 0)?;
         let mut packet = Bytes::from(buffer.to_vec());
 
         // 将状态同步到实例内部
+        self.header_size = LEGACY_HEADER_SIZE;
+        self.version = 0;
         self.free_start = packet.get_u64();
         self.free_end = packet.get_u64();
         self.size = packet.get_u64();
-        
         Ok(())
     }
 }
+
+/// 合并连续分片
+///
+/// 将物理位置连续的分片合并为起始位置和分片数量
+fn runs(offsets: &[u64], chunk_size: u64) -> Vec<(u64, usize)> {
+    let mut runs: Vec<(u64, usize)> = Vec::new();
+    for offset in offsets.iter().copied() {
+        match runs.last_mut() {
+            Some((start, count)) if *start + *count as u64 * chunk_size == offset => *count += 1,
+            _ => runs.push((offset, 1)),
+        }
+    }
+
+    runs
+}
+
+/// 编码文件头
+fn encode_header(header: &Header) -> BytesMut {
+    let mut packet = BytesMut::with_capacity(SLOT_DATA_SIZE);
+    packet.extend_from_slice(MAGIC);
+    packet.put_u32(VERSION);
+    packet.put_u16(header.id);
+    packet.put_u64(header.chunk_size);
+    packet.put_u64(header.seq);
+    packet.put_u64(header.free_start);
+    packet.put_u64(header.free_end);
+    packet.put_u64(header.size);
+    let crc = crc32c::crc32c(&packet);
+    packet.put_u32(crc);
+    packet
+}
+
+/// 解码文件头
+///
+/// 魔数，版本或者校验码不正确的时候返回空
+#[rustfmt::skip]
+fn decode_header(chunk: &[u8]) -> Option<Header> {
+    let size = SLOT_DATA_SIZE - 4;
+    let crc = u32::from_be_bytes([
+        chunk[size], 
+        chunk[size + 1], 
+        chunk[size + 2], 
+        chunk[size + 3]
+    ]);
+
+    if &chunk[..8] != MAGIC || crc32c::crc32c(&chunk[..size]) != crc {
+        return None;
+    }
+
+    let mut packet = &chunk[8..size];
+    if packet.get_u32() != VERSION {
+        return None;
+    }
+
+    Some(Header {
+        id: packet.get_u16(),
+        chunk_size: packet.get_u64(),
+        seq: packet.get_u64(),
+        free_start: packet.get_u64(),
+        free_end: packet.get_u64(),
+        size: packet.get_u64(),
+    })
+}
@@ -0,0 +1,214 @@
+use anyhow::{anyhow, Result};
+use io_uring::{opcode, squeue, types, IoUring};
+use std::os::unix::fs::FileExt;
+use std::os::unix::io::AsRawFd;
+use std::fs::File;
+use std::io::Error;
+
+/// 队列深度
+///
+/// 单次提交的最大请求数量
+const DEPTH: usize = 64;
+
+/// io_uring队列
+///
+/// 将多个分片的读写一次提交给内核，
+/// 等待全部完成之后返回，
+/// 队列本身出现错误之后标记为不可用，
+/// 调用方需要改用普通读写
+pub struct Ring {
+    ring: IoUring,
+    poisoned: bool,
+}
+
+impl Ring {
+    /// 创建队列
+    ///
+    /// 内核不支持io_uring的时候返回错误
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Ring;
+    ///
+    /// let ring = Ring::new().unwrap();
+    /// ```
+    pub fn new() -> Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(DEPTH as u32)?,
+            poisoned: false,
+        })
+    }
+
+    /// 队列是否已经不可用
+    ///
+    /// 提交或者等待请求失败之后队列中可能残留请求，
+    /// 这时候不能继续使用
+    pub fn is_poisoned(&self) -> bool {
+        self.poisoned
+    }
+
+    /// 批量读取
+    ///
+    /// 每个缓冲区从对应的位置读满，
+    /// 内核返回的长度不足的时候补充读取剩余部分
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Ring;
+    /// use std::fs::File;
+    ///
+    /// let file = File::open("1.track").unwrap();
+    /// let mut ring = Ring::new().unwrap();
+    /// let mut a = [0u8; 4096];
+    /// let mut b = [0u8; 4096];
+    /// ring.read(&file, &mut [(8192, &mut a[..]), (12288, &mut b[..])]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn read(&mut self, file: &File, chunks: &mut [(u64, &mut [u8])]) -> Result<()> {
+        let fd = types::Fd(file.as_raw_fd());
+        for batch in chunks.chunks_mut(DEPTH) {
+            let entries: Vec<squeue::Entry> = batch
+                .iter_mut()
+                .enumerate()
+                .map(|(i, (offset, chunk))| {
+                    opcode::Read::new(fd, chunk.as_mut_ptr(), chunk.len() as u32)
+                        .offset(*offset)
+                        .build()
+                        .user_data(i as u64)
+                })
+                .collect();
+
+            let sizes = self.submit(&entries)?;
+            for ((offset, chunk), size) in batch.iter_mut().zip(sizes) {
+                if size < chunk.len() {
+                    file.read_exact_at(&mut chunk[size..], *offset + size as u64)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 批量写入
+    ///
+    /// 每个缓冲区完整写入对应的位置，
+    /// 内核返回的长度不足的时候补充写入剩余部分
+    ///
+    /// # Examples
+    ///
+    /// ```no_run
+    /// use super::Ring;
+    /// use std::fs::File;
+    ///
+    /// let file = File::create("1.track").unwrap();
+    /// let mut ring = Ring::new().unwrap();
+    /// ring.write(&file, &[(8192, &[0u8; 4096][..])]).unwrap();
+    /// ```
+    #[rustfmt::skip]
+    pub fn write(&mut self, file: &File, chunks: &[(u64, &[u8])]) -> Result<()> {
+        let fd = types::Fd(file.as_raw_fd());
+        for batch in chunks.chunks(DEPTH) {
+            let entries: Vec<squeue::Entry> = batch
+                .iter()
+                .enumerate()
+                .map(|(i, (offset, chunk))| {
+                    opcode::Write::new(fd, chunk.as_ptr(), chunk.len() as u32)
+                        .offset(*offset)
+                        .build()
+                        .user_data(i as u64)
+                })
+                .collect();
+
+            let sizes = self.submit(&entries)?;
+            for ((offset, chunk), size) in batch.iter().zip(sizes) {
+                if size < chunk.len() {
+                    file.write_all_at(&chunk[size..], *offset + size as u64)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// 提交请求
+    ///
+    /// 等待所有请求完成，
+    /// 按照提交顺序返回每个请求完成的长度，
+    /// 需要取出所有完成事件之后才能返回错误，
+    /// 否则内核可能还在使用调用方的缓冲区，
+    /// 残留的事件也会影响下次提交
+    #[rustfmt::skip]
+    fn submit(&mut self, entries: &[squeue::Entry]) -> Result<Vec<usize>> {
+        if self.poisoned {
+            return Err(anyhow!("io_uring poisoned"));
+        }
+
+        unsafe {
+            self.ring
+                .submission()
+                .push_multiple(entries)
+                .map_err(|_| anyhow!("submission queue full"))?;
+        }
+
+        // 等待被信号中断或者内核暂时繁忙的时候重新等待，
+        // 直到所有请求都已经完成
+        let mut sizes = vec![0; entries.len()];
+        let mut error = None;
+        let mut done = 0;
+    while done < entries.len() {
+        if let Err(e) = self.ring.submit_and_wait(entries.len() - done) {
+            match e.raw_os_error() {
+                Some(libc::EINTR) | Some(libc::EAGAIN) | Some(libc::EBUSY) => (),
+                _ => {
+                    self.poisoned = true;
+                    error = error.or(Some(e));
+                    break;
+                }
+            }
+        }
+
+        done += self.reap(&mut sizes, &mut error);
+    }
+
+        // 队列出现错误的时候，
+        // 已经被内核取走的请求仍然在使用缓冲区，
+        // 不能再通过队列等待，只能轮询完成队列，
+        // 让出线程的时候内核会处理完成事件
+        if self.poisoned {
+            let submitted = entries.len() - self.ring.submission().len();
+        while done < submitted {
+            done += self.reap(&mut sizes, &mut error);
+            std::thread::yield_now();
+        }
+        }
+
+        match error {
+            Some(e) => Err(e.into()),
+            None => Ok(sizes)
+        }
+    }
+
+    /// 取出完成事件
+    ///
+    /// 记录每个请求完成的长度以及第一个错误，
+    /// 返回取出的事件数量
+    fn reap(&mut self, sizes: &mut [usize], error: &mut Option<Error>) -> usize {
+        let mut count = 0;
+        for event in self.ring.completion() {
+            let result = event.result();
+            if result < 0 {
+                if error.is_none() {
+                    *error = Some(Error::from_raw_os_error(-result));
+                }
+            } else if let Some(size) = sizes.get_mut(event.user_data() as usize) {
+                *size = result as usize;
+            }
+
+            count += 1;
+        }
+
+        count
+    }
+}
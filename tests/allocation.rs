@@ -0,0 +1,177 @@
+use physeter::{Allocation, Kernel, KernelOptions};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+const STRATEGIES: [Allocation; 4] = [
+    Allocation::Sequential,
+    Allocation::RoundRobin,
+    Allocation::LeastFull,
+    Allocation::Affinity,
+];
+
+fn directory(name: &str, allocation: Allocation) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-allocation-{}-{:?}", name, allocation));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, allocation: Allocation) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .allocation(allocation)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+/// 以键为内容重复填充，
+/// 用于在轨道文件中查找数据
+fn data(key: &str, size: usize) -> Vec<u8> {
+    format!("<{:>14}>", key).into_bytes().into_iter().cycle().take(size).collect()
+}
+
+/// 查找数据所在的轨道
+fn locate(path: &Path, key: &str) -> Vec<u16> {
+    let marker = data(key, 64);
+    (1..16u16)
+        .filter(|id| {
+            std::fs::read(path.join(format!("{}.track", id)))
+                .map(|x| x.windows(64).any(|w| w == &marker[..]))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+/// 写入跨越三个轨道的数据之后删除，
+/// 三个轨道都有相同的剩余空间
+fn prepare(path: &Path, kernel: &Kernel) {
+    kernel.write(b"fill", &data("fill", 2_500_000)[..]).unwrap();
+    assert_eq!(locate(path, "fill"), vec![1, 2, 3]);
+    kernel.delete(b"fill").unwrap();
+}
+
+fn write(kernel: &Kernel, key: &str, size: usize) {
+    kernel.write(key.as_bytes(), &data(key, size)[..]).unwrap();
+}
+
+#[test]
+fn strategies() {
+    for allocation in STRATEGIES {
+        let path = directory("strategy", allocation);
+        let kernel = Arc::new(open(&path, allocation));
+        prepare(&path, &kernel);
+
+        write(&kernel, "x", 10_000);
+        match allocation {
+            Allocation::Affinity => {
+                let kernel = kernel.clone();
+                std::thread::spawn(move || write(&kernel, "y", 20_000)).join().unwrap();
+            },
+            _ => write(&kernel, "y", 20_000)
+        }
+
+        write(&kernel, "z", 10_000);
+        let tracks: Vec<_> = ["x", "y", "z"].iter().map(|x| locate(&path, x)).collect();
+        let expected = match allocation {
+            Allocation::Sequential => [vec![1], vec![1], vec![1]],
+            Allocation::RoundRobin => [vec![1], vec![2], vec![3]],
+            Allocation::LeastFull => [vec![1], vec![2], vec![3]],
+            Allocation::Affinity => [vec![3], vec![1], vec![3]],
+        };
+
+        assert_eq!(tracks, expected, "{:?}", allocation);
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+#[test]
+fn least_full() {
+    let path = directory("least", Allocation::LeastFull);
+    let kernel = open(&path, Allocation::LeastFull);
+    prepare(&path, &kernel);
+
+    // 剩余空间相同的时候选择ID最小的轨道
+    write(&kernel, "p", 600_000);
+    write(&kernel, "x", 10_000);
+    write(&kernel, "y", 20_000);
+    write(&kernel, "z", 4_000);
+    assert_eq!(locate(&path, "p"), vec![1]);
+    assert_eq!(locate(&path, "x"), vec![2]);
+    assert_eq!(locate(&path, "y"), vec![3]);
+    assert_eq!(locate(&path, "z"), vec![2]);
+}
+
+/// 输出一部分数据之后等待信号
+struct Gate {
+    data: Vec<u8>,
+    offset: usize,
+    signal: Option<mpsc::Receiver<()>>,
+}
+
+impl Read for Gate {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.offset >= 20_000 {
+            if let Some(signal) = self.signal.take() {
+                let _ = signal.recv();
+            }
+        }
+
+        let size = std::cmp::min(buf.len(), self.data.len() - self.offset);
+        buf[..size].copy_from_slice(&self.data[self.offset..self.offset + size]);
+        self.offset += size;
+        Ok(size)
+    }
+}
+
+#[test]
+fn leases() {
+    for allocation in STRATEGIES {
+        let path = directory("lease", allocation);
+        let kernel = Arc::new(open(&path, allocation));
+        prepare(&path, &kernel);
+
+        // 写入中途阻塞的写入流持有轨道租约，
+        // 其他写入流不会分配到这个轨道
+        let (release, signal) = mpsc::channel();
+        let (started, wait) = mpsc::channel();
+        let blocked = {
+            let kernel = kernel.clone();
+            std::thread::spawn(move || {
+                let gate = Gate {
+                    data: data("blocked", 100_000),
+                    signal: Some(signal),
+                    offset: 0,
+                };
+
+                started.send(()).unwrap();
+                kernel.write(b"blocked", gate).unwrap();
+            })
+        };
+
+        wait.recv().unwrap();
+        std::thread::sleep(Duration::from_millis(200));
+        for key in ["a", "b", "c"] {
+            write(&kernel, key, 10_000);
+        }
+
+        release.send(()).unwrap();
+        blocked.join().unwrap();
+
+        let leased = locate(&path, "blocked");
+        assert_eq!(leased.len(), 1, "{:?}", allocation);
+        for key in ["a", "b", "c"] {
+            let tracks = locate(&path, key);
+            assert_eq!(tracks.len(), 1, "{:?}", allocation);
+            assert_ne!(tracks, leased, "{:?} {}", allocation, key);
+        }
+
+        let mut output = Vec::new();
+        kernel.read(b"blocked", &mut output).unwrap();
+        assert_eq!(output, data("blocked", 100_000));
+    }
+}
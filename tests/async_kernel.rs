@@ -0,0 +1,112 @@
+#![cfg(feature = "tokio")]
+
+use physeter::{AsyncKernel, Kernel};
+use std::io::Error;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::future::Future;
+use std::time::Duration;
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio::runtime::Runtime;
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn kernel(name: &str) -> AsyncKernel {
+    let path = std::env::temp_dir().join(format!("physeter-async-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    AsyncKernel::new(Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap())
+}
+
+/// 在多线程运行时中执行
+fn block_on<F: Future>(future: F) -> F::Output {
+    Runtime::new().unwrap().block_on(future)
+}
+
+/// 返回指定次数的数据之后，
+/// 根据`fail`返回错误或者一直等待
+struct Source {
+    count: usize,
+    fail: bool,
+}
+
+impl AsyncRead for Source {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        _: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        if self.count == 0 {
+            return match self.fail {
+                true => Poll::Ready(Err(Error::other("boom"))),
+                false => Poll::Pending,
+            };
+        }
+
+        self.count -= 1;
+        buf.put_slice(&[7u8; 5000]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[test]
+fn round_trip() {
+    block_on(async {
+        let kernel = kernel("round-trip");
+        let source = data(300_000);
+        kernel.write(b"a", &source[..]).await.unwrap();
+        assert!(kernel.write(b"a", &source[..]).await.is_err());
+
+        let mut output = Vec::new();
+        kernel.read(b"a", &mut output).await.unwrap();
+        assert_eq!(output, source);
+
+        kernel.put(b"a", &source[..1000]).await.unwrap();
+        let mut output = Vec::new();
+        kernel.read(b"a", &mut output).await.unwrap();
+        assert_eq!(output, &source[..1000]);
+
+        kernel.delete(b"a").await.unwrap();
+        assert!(kernel.delete(b"a").await.is_err());
+        assert!(kernel.kernel().fsck(false).unwrap().is_clean());
+    })
+}
+
+#[test]
+fn stream_error() {
+    block_on(async {
+        let kernel = kernel("stream-error");
+        let source = Source { count: 100, fail: true };
+        assert!(kernel.write(b"b", source).await.is_err());
+        assert!(kernel.read(b"b", &mut Vec::new()).await.is_err());
+        assert!(kernel.kernel().fsck(false).unwrap().is_clean());
+    })
+}
+
+#[test]
+fn cancelled_upload() {
+    block_on(async {
+        let kernel = kernel("cancelled");
+        let task = {
+            let kernel = kernel.clone();
+            tokio::spawn(async move {
+                kernel.put(b"c", Source { count: 100, fail: false }).await
+            })
+        };
+
+        tokio::time::sleep(Duration::from_millis(200)).await;
+        task.abort();
+        assert!(task.await.unwrap_err().is_cancelled());
+
+        // 读取需要等待阻塞线程释放键锁，
+        // 这时候写入已经失败
+        let inner = kernel.kernel().clone();
+        let result = tokio::task::spawn_blocking(move || inner.read(b"c", Vec::new()))
+            .await
+            .unwrap();
+        assert!(result.is_err());
+        assert!(kernel.kernel().fsck(false).unwrap().is_clean());
+    })
+}
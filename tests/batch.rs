@@ -0,0 +1,119 @@
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::path::{Path, PathBuf};
+
+const FORMATS: [ChunkFormat; 2] = [ChunkFormat::Plain, ChunkFormat::Crc32c];
+
+fn directory(name: &str, format: ChunkFormat, direct: bool) -> PathBuf {
+    let name = format!("physeter-batch-{}-{:?}-{}", name, format, direct);
+    let path = std::env::temp_dir().join(name);
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, format: ChunkFormat, direct: bool) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(format)
+        .direct_io(direct)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+/// 以键和位置为内容填充，
+/// 分片错位的时候内容不一致，
+/// 同时可以在轨道文件中查找数据
+fn data(key: &str, size: usize) -> Vec<u8> {
+    (0..size / 16 + 1)
+        .flat_map(|i| format!("<{:>6}:{:>7}>", key, i).into_bytes())
+        .take(size)
+        .collect()
+}
+
+/// 查找数据所在的轨道
+fn locate(path: &Path, key: &str) -> Vec<u16> {
+    let marker = format!("<{:>6}:", key).into_bytes();
+    (1..16u16)
+        .filter(|id| {
+            std::fs::read(path.join(format!("{}.track", id)))
+                .map(|x| x.windows(marker.len()).any(|w| w == &marker[..]))
+                .unwrap_or(false)
+        })
+        .collect()
+}
+
+fn check(kernel: &Kernel, objects: &[(&str, usize)]) {
+    for (key, size) in objects {
+        let expected = data(key, *size);
+        let mut output = Vec::new();
+        kernel.read(key.as_bytes(), &mut output).unwrap();
+        assert_eq!(output, expected);
+
+        let offset = size / 2;
+        let mut output = Vec::new();
+        kernel.read_range(key.as_bytes(), offset as u64, 100_000, &mut output).unwrap();
+        assert_eq!(output, &expected[offset..std::cmp::min(offset + 100_000, *size)]);
+    }
+
+    assert!(kernel.fsck(false).unwrap().is_clean());
+}
+
+/// 连续分配的分片跨越轨道
+///
+/// 写入队列比一个轨道的分片数量更大，
+/// 所以只有在轨道变化的时候才会写入队列，
+/// 两个轨道的数据都必须完整并且位置正确
+#[test]
+fn coalesce_across_tracks() {
+    for format in FORMATS {
+    for direct in [false, true] {
+        let path = directory("tail", format, direct);
+        let kernel = open(&path, format, direct);
+        let objects = [("head", 600_000), ("span", 900_000), ("after", 10_000)];
+        for (key, size) in objects {
+            kernel.write(key.as_bytes(), &data(key, size)[..]).unwrap();
+        }
+
+        assert_eq!(locate(&path, "head"), vec![1]);
+        assert_eq!(locate(&path, "span"), vec![1, 2]);
+        check(&kernel, &objects);
+
+        drop(kernel);
+        check(&open(&path, format, direct), &objects);
+    }
+    }
+}
+
+/// 空闲分片和尾部分片混合跨越轨道
+///
+/// 先复用第一个轨道中不连续的空闲分片，
+/// 再从尾部连续分配，
+/// 最后转移到第二个轨道
+#[test]
+fn coalesce_free_across_tracks() {
+    for format in FORMATS {
+    for direct in [false, true] {
+        let path = directory("free", format, direct);
+        let kernel = open(&path, format, direct);
+        let keys = ["a", "b", "c", "d", "e", "f"];
+        for key in keys {
+            kernel.write(key.as_bytes(), &data(key, 100_000)[..]).unwrap();
+        }
+
+        for key in keys.iter().step_by(2) {
+            kernel.delete(key.as_bytes()).unwrap();
+        }
+
+        let objects = [("b", 100_000), ("d", 100_000), ("f", 100_000), ("span", 1_000_000)];
+        kernel.write(b"span", &data("span", 1_000_000)[..]).unwrap();
+        assert_eq!(locate(&path, "span"), vec![1, 2]);
+        check(&kernel, &objects);
+
+        drop(kernel);
+        check(&open(&path, format, direct), &objects);
+    }
+    }
+}
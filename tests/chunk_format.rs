@@ -0,0 +1,186 @@
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use sha2::{Digest, Sha256};
+use std::fs::OpenOptions;
+use std::io::{Error, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+const FORMATS: [ChunkFormat; 2] = [ChunkFormat::Plain, ChunkFormat::Crc32c];
+
+fn data(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str, format: ChunkFormat) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-format-{}-{:?}", name, format));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, format: ChunkFormat) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(format)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+fn read(kernel: &Kernel, key: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    kernel.read(key, &mut output).unwrap();
+    output
+}
+
+fn read_range(kernel: &Kernel, key: &[u8], offset: u64, len: u64) -> Vec<u8> {
+    let mut output = Vec::new();
+    kernel.read_range(key, offset, len, &mut output).unwrap();
+    output
+}
+
+/// 输出指定次数的数据之后返回错误
+struct Failing(usize);
+
+impl Read for Failing {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0 == 0 {
+            return Err(Error::other("boom"));
+        }
+
+        self.0 -= 1;
+        let size = std::cmp::min(buf.len(), 3000);
+        buf[..size].fill(9);
+        Ok(size)
+    }
+}
+
+#[test]
+fn put_append_delete() {
+    for format in FORMATS {
+        let path = directory("round-trip", format);
+        let kernel = open(&path, format);
+
+        // 长度覆盖空数据，不满一个分片以及跨越多个轨道
+        for (index, size) in [0, 1, 4000, 4096, 100_000, 1_500_000].iter().enumerate() {
+            let key = format!("put-{}", index);
+            let source = data(*size, index);
+            kernel.put(key.as_bytes(), &source[..]).unwrap();
+            assert_eq!(read(&kernel, key.as_bytes()), source, "{:?} {}", format, size);
+
+            let meta = kernel.stat(key.as_bytes()).unwrap();
+            assert_eq!(meta.size, *size as u64);
+            assert!(meta.digest.is_some());
+        }
+
+        // 多次追加，
+        // 每次追加都会重写尾部分片
+        let mut expected = Vec::new();
+        for (index, size) in [10, 5000, 1, 4086, 70_000].iter().enumerate() {
+            let chunk = data(*size, index);
+            kernel.append(b"log", &chunk[..]).unwrap();
+            expected.extend_from_slice(&chunk);
+            assert_eq!(read(&kernel, b"log"), expected, "{:?} {}", format, size);
+        }
+
+        // 追加之后摘要覆盖完整数据
+        let meta = kernel.stat(b"log").unwrap();
+        assert_eq!(meta.size, expected.len() as u64);
+        assert_eq!(meta.digest.unwrap()[..], Sha256::digest(&expected)[..]);
+
+        // 替换之后摘要重新计算
+        let source = data(20_000, 7);
+        kernel.put(b"log", &source[..]).unwrap();
+        assert_eq!(read(&kernel, b"log"), source);
+        assert!(kernel.stat(b"log").unwrap().digest.is_some());
+
+        kernel.delete(b"log").unwrap();
+        assert!(kernel.read(b"log", Vec::new()).is_err());
+        assert!(kernel.delete(b"log").is_err());
+        assert!(kernel.fsck(false).unwrap().is_clean());
+        drop(kernel);
+
+        // 重新打开之后数据不变
+        let kernel = open(&path, format);
+        assert_eq!(read(&kernel, b"put-4"), data(100_000, 4));
+        assert!(kernel.stat(b"log").is_err());
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+#[test]
+fn range_reads() {
+    for format in FORMATS {
+        let path = directory("range", format);
+        let kernel = open(&path, format);
+        let source = data(1_500_000, 3);
+        kernel.put(b"a", &source[..1_000_000]).unwrap();
+        kernel.append(b"a", &source[1_000_000..]).unwrap();
+
+        let diff_size = 4096 - format.header_size();
+        let ranges = [
+            (0, u64::MAX),
+            (0, 1),
+            (diff_size - 1, 2),
+            (diff_size, diff_size),
+            (999_999, 10),
+            (1_048_000, 100_000),
+            (1_499_999, 100),
+            (1_500_000, 100),
+            (2_000_000, 100),
+            (100, 0),
+        ];
+
+        for (offset, len) in ranges {
+            let start = std::cmp::min(offset as usize, source.len());
+            let end = std::cmp::min(offset.saturating_add(len) as usize, source.len());
+            let output = read_range(&kernel, b"a", offset, len);
+            assert_eq!(output, &source[start..end], "{:?} {} {}", format, offset, len);
+        }
+    }
+}
+
+#[test]
+fn failed_streams_keep_previous_data() {
+    for format in FORMATS {
+        let path = directory("failed", format);
+        let kernel = open(&path, format);
+        let source = data(10_000, 1);
+        kernel.put(b"a", &source[..]).unwrap();
+
+        assert!(kernel.put(b"a", Failing(5)).is_err());
+        assert_eq!(read(&kernel, b"a"), source);
+
+        assert!(kernel.append(b"a", Failing(5)).is_err());
+        assert_eq!(read(&kernel, b"a"), source);
+        assert_eq!(kernel.stat(b"a").unwrap().size, source.len() as u64);
+
+        assert!(kernel.write(b"b", Failing(5)).is_err());
+        assert!(kernel.stat(b"b").is_err());
+        assert!(kernel.fsck(false).unwrap().is_clean());
+        drop(kernel);
+
+        let kernel = open(&path, format);
+        assert_eq!(read(&kernel, b"a"), source);
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+#[test]
+fn checksum_detects_corruption() {
+    let path = directory("corruption", ChunkFormat::Crc32c);
+    let kernel = open(&path, ChunkFormat::Crc32c);
+    kernel.put(b"a", &data(10_000, 2)[..]).unwrap();
+    drop(kernel);
+
+    // 修改第一个分片中的数据
+    let file = OpenOptions::new().write(true).open(path.join("1.track")).unwrap();
+    file.write_all_at(&[0xff; 16], 8192 + 100).unwrap();
+
+    let kernel = open(&path, ChunkFormat::Crc32c);
+    assert!(kernel.read(b"a", Vec::new()).is_err());
+    assert!(kernel.read_range(b"a", 0, 10, Vec::new()).is_err());
+    assert_eq!(read_range(&kernel, b"a", 5000, 100), &data(10_000, 2)[5000..5100]);
+}
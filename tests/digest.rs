@@ -0,0 +1,94 @@
+use physeter::Kernel;
+use rocksdb::DB;
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+fn data(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-digest-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> Kernel {
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap()
+}
+
+fn etag(data: &[u8]) -> String {
+    Sha256::digest(data).iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+/// 将索引项改写为没有元数据的旧版本格式
+fn legacy(path: &Path, key: &[u8]) {
+    let db = DB::open_default(path.join("index")).unwrap();
+    let value = db.get(key).unwrap().unwrap();
+    assert_eq!(value[0], 0xFF);
+    let size = u32::from_be_bytes([value[1], value[2], value[3], value[4]]) as usize;
+    db.put(key, &value[5 + size..]).unwrap();
+}
+
+#[test]
+fn write_digest() {
+    let path = directory("write");
+    let kernel = open(&path);
+    for (index, size) in [0, 1, 63, 64, 65, 4086, 100_000].iter().enumerate() {
+        let key = format!("key-{}", index);
+        let source = data(*size, index);
+        kernel.write(key.as_bytes(), &source[..]).unwrap();
+        assert_eq!(kernel.stat(key.as_bytes()).unwrap().etag(), Some(etag(&source)));
+    }
+}
+
+#[test]
+fn append_digest() {
+    let path = directory("append");
+    let kernel = open(&path);
+
+    // 追加长度覆盖不满一个分组，
+    // 正好填满分组以及跨越多个分片
+    let mut expected = Vec::new();
+    for (index, size) in [0, 10, 54, 64, 1, 4086, 127, 70_000, 3].iter().enumerate() {
+        let chunk = data(*size, index);
+        kernel.append(b"log", &chunk[..]).unwrap();
+        expected.extend_from_slice(&chunk);
+
+        let meta = kernel.stat(b"log").unwrap();
+        assert_eq!(meta.size, expected.len() as u64);
+        assert_eq!(meta.etag(), Some(etag(&expected)), "{}", size);
+    }
+
+    // 摘要状态随元数据一起持久化
+    drop(kernel);
+    let kernel = open(&path);
+    let chunk = data(1000, 99);
+    kernel.append(b"log", &chunk[..]).unwrap();
+    expected.extend_from_slice(&chunk);
+    assert_eq!(kernel.stat(b"log").unwrap().etag(), Some(etag(&expected)));
+    assert!(kernel.scrub().unwrap().damaged.is_empty());
+}
+
+#[test]
+fn append_legacy_digest() {
+    let path = directory("legacy");
+    let kernel = open(&path);
+    let source = data(10_000, 1);
+    kernel.write(b"a", &source[..]).unwrap();
+    drop(kernel);
+
+    legacy(&path, b"a");
+    let kernel = open(&path);
+    assert_eq!(kernel.stat(b"a").unwrap().etag(), None);
+
+    // 旧版本数据第一次追加的时候读取全部数据计算摘要
+    let mut expected = source;
+    for (index, size) in [100, 5000].iter().enumerate() {
+        let chunk = data(*size, index);
+        kernel.append(b"a", &chunk[..]).unwrap();
+        expected.extend_from_slice(&chunk);
+        assert_eq!(kernel.stat(b"a").unwrap().etag(), Some(etag(&expected)));
+    }
+}
@@ -0,0 +1,132 @@
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::path::{Path, PathBuf};
+
+const FORMATS: [ChunkFormat; 2] = [ChunkFormat::Plain, ChunkFormat::Crc32c];
+const HEADER_SIZE: usize = 8192;
+
+fn data(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str, format: ChunkFormat) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-direct-{}-{:?}", name, format));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, format: ChunkFormat, direct: bool) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(format)
+        .direct_io(direct)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+/// 制造不对齐的写入
+///
+/// 奇数长度的对象留下不满的尾部分片，
+/// 删除之后链接空闲链表只改写分片头部，
+/// 覆盖写入复用空闲分片，
+/// 追加从不满的尾部分片继续写入，
+/// 返回所有仍然存在的对象
+fn scribble(kernel: &Kernel, format: ChunkFormat) -> Vec<(Vec<u8>, Vec<u8>)> {
+    let diff_size = match format {
+        ChunkFormat::Plain => 4086,
+        ChunkFormat::Crc32c => 4082,
+    };
+
+    let sizes = [1, 7, 4095, diff_size - 1, diff_size + 1, 3 * diff_size + 513, 100_001];
+    let mut objects = Vec::new();
+    for (index, size) in sizes.iter().enumerate() {
+        let key = format!("{}", index).into_bytes();
+        let value = data(*size, index);
+        kernel.write(&key, &value[..]).unwrap();
+        objects.push((key, value));
+    }
+
+    for (key, _) in objects.iter().step_by(2) {
+        kernel.delete(key).unwrap();
+    }
+
+    objects = objects.into_iter().skip(1).step_by(2).collect();
+    for (index, size) in [5usize, 9_999, 2 * diff_size + 3].iter().enumerate() {
+        let key = format!("new-{}", index).into_bytes();
+        let value = data(*size, index + 100);
+        kernel.write(&key, &value[..]).unwrap();
+        objects.push((key, value));
+    }
+
+    for (key, value) in objects.iter_mut() {
+        for size in [1, 513, diff_size + 7] {
+            let chunk = data(size, size + value.len());
+            kernel.append(key, &chunk[..]).unwrap();
+            value.extend_from_slice(&chunk);
+        }
+    }
+
+    objects
+}
+
+#[test]
+fn unaligned_round_trip() {
+    for format in FORMATS {
+        let path = directory("round-trip", format);
+        let objects = scribble(&open(&path, format, true), format);
+
+        let kernel = open(&path, format, true);
+        for (key, value) in objects.iter() {
+            let mut output = Vec::new();
+            kernel.read(key, &mut output).unwrap();
+            assert_eq!(&output, value);
+
+            let offset = value.len() / 3;
+            let mut output = Vec::new();
+            kernel.read_range(key, offset as u64, 4099, &mut output).unwrap();
+            let end = std::cmp::min(offset + 4099, value.len());
+            assert_eq!(output, &value[offset..end]);
+        }
+
+        for index in [0, 2, 4, 6] {
+            assert!(kernel.read(format!("{}", index).as_bytes(), Vec::new()).is_err());
+        }
+
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+/// 读改写不能覆盖相邻的分片
+///
+/// 同样的操作分别使用缓冲写入和直接写入，
+/// 除去轨道头部之后文件内容必须一致，
+/// 直接写入只会在文件尾部多出补齐对齐的零
+#[test]
+fn unaligned_matches_buffered() {
+    for format in FORMATS {
+        let buffered = directory("buffered", format);
+        let direct = directory("direct", format);
+        scribble(&open(&buffered, format, false), format);
+        scribble(&open(&direct, format, true), format);
+
+        let mut tracks = 0;
+        for entry in std::fs::read_dir(&buffered).unwrap() {
+            let name = entry.unwrap().file_name();
+            if !name.to_str().unwrap().ends_with(".track") {
+                continue;
+            }
+
+            let expected = std::fs::read(buffered.join(&name)).unwrap();
+            let actual = std::fs::read(direct.join(&name)).unwrap();
+            assert!(actual.len() >= expected.len());
+            assert_eq!(&actual[HEADER_SIZE..expected.len()], &expected[HEADER_SIZE..]);
+            assert!(actual[expected.len()..].iter().all(|byte| *byte == 0));
+            tracks += 1;
+        }
+
+        assert!(tracks > 0);
+    }
+}
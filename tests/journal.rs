@@ -0,0 +1,378 @@
+use physeter::Kernel;
+use rocksdb::DB;
+use std::fs::OpenOptions;
+use std::io::{Error, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: u64 = 4096;
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-journal-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> Kernel {
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap()
+}
+
+/// 编码日志记录，
+/// 和内部格式保持一致
+fn record(kind: u8, id: u64, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    packet.extend_from_slice(body);
+    let crc = crc32c::crc32c(&packet);
+    packet.extend_from_slice(&crc.to_be_bytes());
+    packet
+}
+
+fn begin(id: u64, key: &[u8]) -> Vec<u8> {
+    let mut body = (key.len() as u32).to_be_bytes().to_vec();
+    body.extend_from_slice(key);
+    record(1, id, &body)
+}
+
+fn alloc_map(kind: u8, id: u64, track: u16, offsets: &[u64]) -> Vec<u8> {
+    let mut body = 1u32.to_be_bytes().to_vec();
+    body.extend_from_slice(&track.to_be_bytes());
+    body.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+    for offset in offsets {
+        body.extend_from_slice(&offset.to_be_bytes());
+    }
+
+    record(kind, id, &body)
+}
+
+fn alloc(id: u64, track: u16, offsets: &[u64]) -> Vec<u8> {
+    alloc_map(4, id, track, offsets)
+}
+
+fn free(id: u64, track: u16, offsets: &[u64]) -> Vec<u8> {
+    alloc_map(5, id, track, offsets)
+}
+
+fn tail(id: u64, key: &[u8], size: u64, offset: u64, data: &[u8]) -> Vec<u8> {
+    let mut body = (key.len() as u32).to_be_bytes().to_vec();
+    body.extend_from_slice(key);
+    body.extend_from_slice(&size.to_be_bytes());
+    body.extend_from_slice(&1u16.to_be_bytes());
+    body.extend_from_slice(&offset.to_be_bytes());
+    body.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    body.extend_from_slice(data);
+    record(2, id, &body)
+}
+
+/// 在轨道尾部追加没有被引用的分片，
+/// 模拟写入中途崩溃留下的分片
+fn leak(path: &Path, count: u64) -> Vec<u64> {
+    let track = path.join("1.track");
+    let size = std::fs::metadata(&track).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&track).unwrap();
+    file.write_all(&vec![0u8; (count * CHUNK_SIZE) as usize]).unwrap();
+    (0..count).map(|i| size + i * CHUNK_SIZE).collect()
+}
+
+/// 覆盖分片，
+/// 模拟追加写入中途崩溃重写了一半的尾部分片
+fn scribble(path: &Path, offset: u64) {
+    let mut file = OpenOptions::new().write(true).open(path.join("1.track")).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(&[0xAB; CHUNK_SIZE as usize]).unwrap();
+}
+
+fn track_size(path: &Path) -> u64 {
+    std::fs::metadata(path.join("1.track")).unwrap().len()
+}
+
+/// 直接修改索引，
+/// 模拟索引更新之后崩溃
+fn index<F: FnOnce(&DB)>(path: &Path, handle: F) {
+    let db = DB::open_default(path.join("index")).unwrap();
+    handle(&db);
+}
+
+/// 将索引项改写为没有元数据的旧版本格式
+fn legacy(path: &Path, key: &[u8]) {
+    index(path, |db| {
+        let value = db.get(key).unwrap().unwrap();
+        assert_eq!(value[0], 0xFF);
+        let size = u32::from_be_bytes([value[1], value[2], value[3], value[4]]) as usize;
+        db.put(key, &value[5 + size..]).unwrap();
+    });
+}
+
+fn source(size: usize) -> Vec<u8> {
+    (0..size).map(|x| (x % 251) as u8).collect()
+}
+
+fn read(kernel: &Kernel, key: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    kernel.read(key, &mut output).unwrap();
+    output
+}
+
+/// 追加写入在索引更新之前崩溃
+///
+/// 5000字节的数据占用8192和12288两个分片，
+/// 尾部分片保存剩余的914字节
+fn crash_before_index(name: &str, is_legacy: bool, append: usize) {
+    let path = directory(name);
+    let data = source(5000);
+    let kernel = open(&path);
+    kernel.write(b"a", &data[..]).unwrap();
+    drop(kernel);
+
+    if is_legacy {
+        legacy(&path, b"a");
+    }
+
+    let mut records = vec![
+        begin(100, b"a"), 
+        tail(100, b"a", 5000, 12288, &data[4086..])
+    ];
+
+    let offsets = match append > CHUNK_SIZE as usize {
+        true => leak(&path, 2),
+        false => Vec::new()
+    };
+
+    if !offsets.is_empty() {
+        records.push(alloc(100, 1, &offsets));
+    }
+
+    scribble(&path, 12288);
+    journal(&path, &records);
+
+    let kernel = open(&path);
+    assert_eq!(read(&kernel, b"a"), data);
+
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, offsets.len() as u64);
+}
+
+/// 追加写入在索引更新之后崩溃
+fn crash_after_index(name: &str, is_legacy: bool, append: usize) {
+    let path = directory(name);
+    let data = source(5000);
+    let kernel = open(&path);
+    kernel.write(b"a", &data[..]).unwrap();
+    drop(kernel);
+
+    if is_legacy {
+        legacy(&path, b"a");
+    }
+
+    let size = track_size(&path);
+    let kernel = open(&path);
+    kernel.append(b"a", &source(append)[..]).unwrap();
+    drop(kernel);
+
+    let offsets: Vec<u64> = (size..track_size(&path)).step_by(CHUNK_SIZE as usize).collect();
+    let mut records = vec![
+        begin(100, b"a"), 
+        tail(100, b"a", 5000, 12288, &data[4086..])
+    ];
+
+    if !offsets.is_empty() {
+        records.push(alloc(100, 1, &offsets));
+    }
+
+    journal(&path, &records);
+
+    let kernel = open(&path);
+    let mut expected = data.clone();
+    expected.extend_from_slice(&source(append));
+    assert_eq!(read(&kernel, b"a"), expected);
+
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, 0);
+}
+
+fn journal(path: &Path, records: &[Vec<u8>]) {
+    let mut file = OpenOptions::new().append(true).open(path.join("journal")).unwrap();
+    for record in records {
+        file.write_all(record).unwrap();
+    }
+}
+
+struct Failing(usize);
+
+impl Read for Failing {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0 == 0 {
+            return Err(Error::other("boom"));
+        }
+
+        self.0 -= 1;
+        let size = std::cmp::min(buf.len(), 3000);
+        buf[..size].fill(9);
+        Ok(size)
+    }
+}
+
+#[test]
+fn recover_allocated_chunks() {
+    let path = directory("allocated");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    drop(kernel);
+
+    let offsets = leak(&path, 2);
+    journal(&path, &[begin(100, b"b"), alloc(100, 1, &offsets)]);
+
+    let kernel = open(&path);
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, 2);
+
+    let mut output = Vec::new();
+    kernel.read(b"a", &mut output).unwrap();
+    assert_eq!(output, vec![1u8; 10000]);
+}
+
+#[test]
+fn recover_only_touches_journaled_chunks() {
+    let path = directory("targeted");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    drop(kernel);
+
+    let offsets = leak(&path, 3);
+    journal(&path, &[begin(100, b"b"), alloc(100, 1, &offsets[..1])]);
+
+    let kernel = open(&path);
+    let report = kernel.fsck(false).unwrap();
+    assert_eq!(report.free, 1);
+    assert_eq!(report.orphans, vec![(1, offsets[1]), (1, offsets[2])]);
+}
+
+#[test]
+fn recover_append_before_index() {
+    crash_before_index("append-before", false, 5000);
+    crash_before_index("append-before-tail", false, 100);
+}
+
+#[test]
+fn recover_append_before_index_legacy() {
+    crash_before_index("append-before-legacy", true, 5000);
+    crash_before_index("append-before-legacy-tail", true, 100);
+}
+
+#[test]
+fn recover_append_after_index() {
+    crash_after_index("append-after", false, 5000);
+    crash_after_index("append-after-tail", false, 100);
+}
+
+#[test]
+fn recover_append_after_index_legacy() {
+    crash_after_index("append-after-legacy", true, 5000);
+    crash_after_index("append-after-legacy-tail", true, 100);
+}
+
+#[test]
+fn recover_delete_before_index() {
+    let path = directory("delete-before");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 5000][..]).unwrap();
+    drop(kernel);
+
+    journal(&path, &[begin(100, b"a"), free(100, 1, &[8192, 12288])]);
+
+    let kernel = open(&path);
+    assert_eq!(read(&kernel, b"a"), vec![1u8; 5000]);
+
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, 0);
+}
+
+#[test]
+fn recover_delete_after_index() {
+    let path = directory("delete-after");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 5000][..]).unwrap();
+    drop(kernel);
+
+    index(&path, |db| db.delete(b"a").unwrap());
+    journal(&path, &[begin(100, b"a"), free(100, 1, &[8192, 12288])]);
+
+    let kernel = open(&path);
+    assert!(kernel.stat(b"a").is_err());
+
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, 2);
+}
+
+#[test]
+fn recover_store_after_index() {
+    let path = directory("store-after");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 5000][..]).unwrap();
+    kernel.write(b"b", &[2u8; 5000][..]).unwrap();
+    drop(kernel);
+
+    // 覆盖写入已经更新索引，
+    // 但是还没有释放之前的分片
+    index(&path, |db| {
+        let value = db.get(b"b").unwrap().unwrap();
+        db.put(b"a", value).unwrap();
+        db.delete(b"b").unwrap();
+    });
+
+    journal(&path, &[
+        begin(100, b"a"), 
+        alloc(100, 1, &[16384, 20480]), 
+        free(100, 1, &[8192, 12288])
+    ]);
+
+    let kernel = open(&path);
+    assert_eq!(read(&kernel, b"a"), vec![2u8; 5000]);
+
+    let report = kernel.fsck(false).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(report.free, 2);
+}
+
+#[test]
+fn failed_write_commits() {
+    let path = directory("failed");
+    let kernel = open(&path);
+    assert!(kernel.put(b"a", Failing(10)).is_err());
+    assert!(kernel.append(b"b", &b"hello"[..]).is_ok());
+    assert!(kernel.append(b"b", Failing(10)).is_err());
+    assert!(kernel.delete(b"c").is_err());
+    drop(kernel);
+
+    // 每个开始记录都有对应的完成标记
+    let mut buffer = Vec::new();
+    let mut file = std::fs::File::open(path.join("journal")).unwrap();
+    file.read_to_end(&mut buffer).unwrap();
+
+    let mut kinds = Vec::new();
+    let mut chunk = &buffer[..];
+    while !chunk.is_empty() {
+        let size = u32::from_be_bytes([chunk[9], chunk[10], chunk[11], chunk[12]]) as usize;
+        kinds.push(chunk[0]);
+        chunk = &chunk[17 + size..];
+    }
+
+    let begins = kinds.iter().filter(|x| **x == 1).count();
+    let commits = kinds.iter().filter(|x| **x == 3).count();
+    assert_eq!(begins, 3);
+    assert_eq!(commits, 3);
+
+    let kernel = open(&path);
+    assert!(kernel.fsck(false).unwrap().is_clean());
+    assert!(kernel.stat(b"a").is_err());
+
+    let mut output = Vec::new();
+    kernel.read(b"b", &mut output).unwrap();
+    assert_eq!(output, b"hello");
+}
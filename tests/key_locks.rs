@@ -0,0 +1,106 @@
+use physeter::Kernel;
+use std::io::Read;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+fn kernel(name: &str) -> Kernel {
+    let path = std::env::temp_dir().join(format!("physeter-locks-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    Kernel::new(path.to_str().unwrap().to_string(), 64 * 1024 * 1024).unwrap()
+}
+
+/// 收到信号之前一直阻塞的数据流
+struct Blocking(mpsc::Receiver<()>);
+
+impl Read for Blocking {
+    fn read(&mut self, _: &mut [u8]) -> std::io::Result<usize> {
+        let _ = self.0.recv();
+        Ok(0)
+    }
+}
+
+#[test]
+fn keys_do_not_block_each_other() {
+    let kernel = Arc::new(kernel("independent"));
+    let (release, stream) = mpsc::channel();
+    let writer = {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.put(b"blocked", Blocking(stream)).unwrap();
+        })
+    };
+
+    std::thread::sleep(Duration::from_millis(200));
+    let (tx, rx) = mpsc::channel();
+    {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            for i in 0..256u32 {
+                let key = format!("key-{}", i);
+                kernel.put(key.as_bytes(), &i.to_be_bytes()[..]).unwrap();
+                kernel.stat(key.as_bytes()).unwrap();
+            }
+
+            tx.send(()).unwrap();
+        });
+    }
+
+    let done = rx.recv_timeout(Duration::from_secs(5)).is_ok();
+    release.send(()).unwrap();
+    writer.join().unwrap();
+    assert!(done);
+
+    let mut output = Vec::new();
+    kernel.read(b"key-7", &mut output).unwrap();
+    assert_eq!(output, 7u32.to_be_bytes());
+}
+
+/// 先输出数据，
+/// 收到信号之前不会结束的数据流
+struct Pending(usize, mpsc::Receiver<()>);
+
+impl Read for Pending {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.0 == 0 {
+            let _ = self.1.recv();
+            return Ok(0);
+        }
+
+        let size = std::cmp::min(std::cmp::min(buf.len(), self.0), 4096);
+        buf[..size].fill(1);
+        self.0 -= size;
+        Ok(size)
+    }
+}
+
+#[test]
+fn fsck_waits_for_writers() {
+    let kernel = Arc::new(kernel("fsck"));
+    let (release, stream) = mpsc::channel();
+    let writer = {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.put(b"a", Pending(1024 * 1024, stream)).unwrap();
+        })
+    };
+
+    std::thread::sleep(Duration::from_millis(200));
+    let (tx, rx) = mpsc::channel();
+    {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            tx.send(kernel.fsck(false).unwrap()).unwrap();
+        });
+    }
+
+    // 写入完成之前检查不会开始，
+    // 正在写入的分片不会被视为泄露
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    release.send(()).unwrap();
+    writer.join().unwrap();
+
+    let report = rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert!(report.is_clean());
+    assert_eq!(kernel.stat(b"a").unwrap().size, 1024 * 1024);
+}
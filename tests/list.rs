@@ -0,0 +1,136 @@
+use physeter::{Kernel, ObjectMeta};
+use rocksdb::DB;
+use std::path::{Path, PathBuf};
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-list-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> Kernel {
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap()
+}
+
+/// 写入前缀内外的键，
+/// 返回前缀内按照字典序排列的键
+fn fill(kernel: &Kernel) -> Vec<Vec<u8>> {
+    for key in ["a", "b", "b0", "ba", "c"] {
+        kernel.write(key.as_bytes(), &b"x"[..]).unwrap();
+    }
+
+    let mut keys = Vec::new();
+    for i in (0..25).rev() {
+        let key = format!("b/{}", i);
+        kernel.write(key.as_bytes(), &vec![1u8; i * 100][..]).unwrap();
+        keys.push(key.into_bytes());
+    }
+
+    keys.sort();
+    keys
+}
+
+#[test]
+fn paginate_with_prefix() {
+    let path = directory("paginate");
+    let kernel = open(&path);
+    let expected = fill(&kernel);
+
+    // 使用上一页最后一个键继续列出
+    let mut keys = Vec::new();
+    let mut pages = 0;
+    loop {
+        let page = kernel.list(b"b/", keys.last().map(|x: &Vec<u8>| &x[..]), 7).unwrap();
+        if page.is_empty() {
+            break;
+        }
+
+        assert!(page.len() <= 7);
+        keys.extend(page);
+        pages += 1;
+    }
+
+    assert_eq!(pages, 4);
+    assert_eq!(keys, expected);
+}
+
+#[test]
+fn start_after_bounds() {
+    let path = directory("bounds");
+    let kernel = open(&path);
+    let expected = fill(&kernel);
+
+    // 不存在的键从之后的第一个键开始
+    assert_eq!(kernel.list(b"b/", Some(b"b/15x"), 2).unwrap(), vec![b"b/16".to_vec(), b"b/17".to_vec()]);
+
+    // 前缀之前的键从前缀开始，
+    // 前缀之后的键没有结果
+    assert_eq!(kernel.list(b"b/", Some(b"a"), 100).unwrap(), expected);
+    assert!(kernel.list(b"b/", Some(b"b0"), 100).unwrap().is_empty());
+    assert!(kernel.list(b"b/", Some(b"b/9"), 100).unwrap().is_empty());
+    assert!(kernel.list(b"b/", None, 0).unwrap().is_empty());
+    assert!(kernel.list(b"d", None, 100).unwrap().is_empty());
+
+    // 空前缀列出所有键
+    let all = kernel.list(b"", None, 100).unwrap();
+    assert_eq!(all.len(), 30);
+    assert_eq!(&all[..2], &[b"a".to_vec(), b"b".to_vec()]);
+    assert_eq!(&all[2..27], &expected[..]);
+    assert_eq!(&all[27..], &[b"b0".to_vec(), b"ba".to_vec(), b"c".to_vec()]);
+
+    // 删除的键不再列出
+    kernel.delete(b"b/3").unwrap();
+    let keys = kernel.list(b"b/", Some(b"b/24"), 3).unwrap();
+    assert_eq!(keys, vec![b"b/4".to_vec(), b"b/5".to_vec(), b"b/6".to_vec()]);
+}
+
+#[test]
+fn paginate_with_meta() {
+    let path = directory("meta");
+    let kernel = open(&path);
+    let expected = fill(&kernel);
+    let meta = ObjectMeta::new(Some("text/plain".to_string()));
+    kernel.put_with_meta(b"b/0", &b"hello"[..], meta).unwrap();
+    drop(kernel);
+
+    // 旧版本索引项的元数据从轨道中计算
+    {
+        let db = DB::open_default(path.join("index")).unwrap();
+        let value = db.get(b"b/10").unwrap().unwrap();
+        let size = u32::from_be_bytes([value[1], value[2], value[3], value[4]]) as usize;
+        db.put(b"b/10", &value[5 + size..]).unwrap();
+    }
+
+    let kernel = open(&path);
+    let mut entries: Vec<(Vec<u8>, ObjectMeta)> = Vec::new();
+    loop {
+        let start_after = entries.last().map(|(x, _)| &x[..]);
+        let page = kernel.list_with_meta(b"b/", start_after, 10).unwrap();
+        if page.is_empty() {
+            break;
+        }
+
+        entries.extend(page);
+    }
+
+    let keys: Vec<_> = entries.iter().map(|(x, _)| x.clone()).collect();
+    assert_eq!(keys, expected);
+
+    for (key, meta) in entries.iter() {
+        let index: usize = std::str::from_utf8(&key[2..]).unwrap().parse().unwrap();
+        match index {
+            0 => {
+                assert_eq!(meta.size, 5);
+                assert_eq!(meta.content_type.as_deref(), Some("text/plain"));
+            },
+            10 => {
+                assert_eq!(meta.size, 1000);
+                assert_eq!(meta.create_time, 0);
+            },
+            _ => assert_eq!(meta.size, index as u64 * 100)
+        }
+
+        assert_eq!(meta, &kernel.stat(key).unwrap());
+    }
+}
@@ -0,0 +1,120 @@
+use physeter::{Kernel, ObjectMeta};
+use rocksdb::DB;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-meta-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> Kernel {
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap()
+}
+
+#[test]
+fn write_with_meta() {
+    let path = directory("write");
+    let kernel = open(&path);
+
+    // 长度，分片数量和时间由内部填充
+    let mut meta = ObjectMeta::new(Some("video/mp4".to_string()))
+        .header("owner", "panda")
+        .header("tag", "");
+    meta.size = 1;
+    meta.chunks = 100;
+    meta.create_time = 1;
+    kernel.write_with_meta(b"a", &data(10_000)[..], meta).unwrap();
+
+    let meta = kernel.stat(b"a").unwrap();
+    assert_eq!(meta.size, 10_000);
+    assert_eq!(meta.chunks, 3);
+    assert!(meta.create_time > 1);
+    assert!(meta.update_time >= meta.create_time);
+    assert_eq!(meta.content_type.as_deref(), Some("video/mp4"));
+    assert_eq!(meta.headers.len(), 2);
+    assert_eq!(meta.headers["owner"], "panda");
+    assert_eq!(meta.headers["tag"], "");
+
+    // 元数据随索引一起持久化
+    drop(kernel);
+    let kernel = open(&path);
+    assert_eq!(kernel.stat(b"a").unwrap(), meta);
+
+    kernel.write(b"b", &b""[..]).unwrap();
+    let meta = kernel.stat(b"b").unwrap();
+    assert_eq!(meta.size, 0);
+    assert_eq!(meta.chunks, 0);
+    assert_eq!(meta.content_type, None);
+    assert!(meta.headers.is_empty());
+    assert!(kernel.stat(b"c").is_err());
+}
+
+#[test]
+fn replace_and_append() {
+    let path = directory("replace");
+    let kernel = open(&path);
+    let meta = ObjectMeta::new(Some("text/plain".to_string())).header("owner", "panda");
+    kernel.write_with_meta(b"a", &data(100)[..], meta).unwrap();
+    let created = kernel.stat(b"a").unwrap();
+
+    // 替换保留创建时间，
+    // 其他元数据使用新的值
+    std::thread::sleep(Duration::from_millis(5));
+    let meta = ObjectMeta::new(None).header("version", "2");
+    kernel.put_with_meta(b"a", &data(5000)[..], meta).unwrap();
+
+    let replaced = kernel.stat(b"a").unwrap();
+    assert_eq!(replaced.create_time, created.create_time);
+    assert!(replaced.update_time > created.update_time);
+    assert_eq!(replaced.size, 5000);
+    assert_eq!(replaced.chunks, 2);
+    assert_eq!(replaced.content_type, None);
+    assert_eq!(replaced.headers.len(), 1);
+    assert_eq!(replaced.headers["version"], "2");
+
+    // 追加更新长度和分片数量，
+    // 保留内容类型和自定义头
+    std::thread::sleep(Duration::from_millis(5));
+    kernel.append(b"a", &data(4000)[..]).unwrap();
+
+    let appended = kernel.stat(b"a").unwrap();
+    assert_eq!(appended.create_time, created.create_time);
+    assert!(appended.update_time > replaced.update_time);
+    assert_eq!(appended.size, 9000);
+    assert_eq!(appended.chunks, 3);
+    assert_eq!(appended.headers, replaced.headers);
+}
+
+#[test]
+fn legacy_entry() {
+    let path = directory("legacy");
+    let kernel = open(&path);
+    kernel.write_with_meta(b"a", &data(10_000)[..], ObjectMeta::new(Some("a/b".to_string()))).unwrap();
+    drop(kernel);
+
+    // 旧版本索引项只有分配表
+    {
+        let db = DB::open_default(path.join("index")).unwrap();
+        let value = db.get(b"a").unwrap().unwrap();
+        let size = u32::from_be_bytes([value[1], value[2], value[3], value[4]]) as usize;
+        db.put(b"a", &value[5 + size..]).unwrap();
+    }
+
+    let kernel = open(&path);
+    let meta = kernel.stat(b"a").unwrap();
+    assert_eq!(meta.size, 10_000);
+    assert_eq!(meta.chunks, 3);
+    assert_eq!(meta.content_type, None);
+    assert_eq!(meta.create_time, 0);
+
+    let mut output = Vec::new();
+    kernel.read(b"a", &mut output).unwrap();
+    assert_eq!(output, data(10_000));
+}
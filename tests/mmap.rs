@@ -0,0 +1,129 @@
+#![cfg(feature = "memmap2")]
+
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::path::{Path, PathBuf};
+
+const FORMATS: [ChunkFormat; 2] = [ChunkFormat::Plain, ChunkFormat::Crc32c];
+
+fn data(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str, format: ChunkFormat) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-mmap-{}-{:?}", name, format));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, format: ChunkFormat) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(format)
+        .mmap(true)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+fn read(kernel: &Kernel, key: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    kernel.read(key, &mut output).unwrap();
+    output
+}
+
+/// 轨道增长之后重新映射
+///
+/// 第一次读取按照当时的文件长度映射，
+/// 之后写入的数据超出映射范围，
+/// 同一个实例必须重新映射才能读到
+#[test]
+fn remap_on_growth() {
+    for format in FORMATS {
+        let path = directory("growth", format);
+        let kernel = open(&path, format);
+        let first = data(5000, 1);
+        kernel.write(b"first", &first[..]).unwrap();
+        assert_eq!(read(&kernel, b"first"), first);
+
+        let mut objects = vec![(b"first".to_vec(), first)];
+        for (index, size) in [1, 4096, 70_000, 300_000].iter().enumerate() {
+            let key = format!("{}", index).into_bytes();
+            let value = data(*size, index);
+            kernel.write(&key, &value[..]).unwrap();
+            assert_eq!(read(&kernel, &key), value);
+            objects.push((key, value));
+        }
+
+        let mut output = Vec::new();
+        kernel.read_range(b"3", 200_000, 50_000, &mut output).unwrap();
+        assert_eq!(output, &data(300_000, 3)[200_000..250_000]);
+
+        for (key, value) in objects.iter() {
+            assert_eq!(&read(&kernel, key), value);
+        }
+
+        drop(kernel);
+        let kernel = open(&path, format);
+        for (key, value) in objects.iter() {
+            assert_eq!(&read(&kernel, key), value);
+        }
+
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+/// 追加增长尾部分片
+///
+/// 尾部分片在映射之后被改写，
+/// 新分片追加在映射范围之外，
+/// 每次追加之后读取都必须看到完整的数据
+#[test]
+fn append_after_map() {
+    for format in FORMATS {
+        let path = directory("append", format);
+        let kernel = open(&path, format);
+        let mut expected = data(100, 7);
+        kernel.write(b"log", &expected[..]).unwrap();
+        assert_eq!(read(&kernel, b"log"), expected);
+
+        for size in [1, 3000, 4096, 50_000] {
+            let chunk = data(size, size);
+            kernel.append(b"log", &chunk[..]).unwrap();
+            expected.extend_from_slice(&chunk);
+            assert_eq!(read(&kernel, b"log"), expected);
+        }
+
+        let offset = expected.len() - 10_000;
+        let mut output = Vec::new();
+        kernel.read_range(b"log", offset as u64, 10_000, &mut output).unwrap();
+        assert_eq!(output, &expected[offset..]);
+
+        drop(kernel);
+        let kernel = open(&path, format);
+        assert_eq!(read(&kernel, b"log"), expected);
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
+
+/// 复用空闲分片
+///
+/// 映射是共享的，
+/// 覆盖已经映射的分片之后读取的是新数据
+#[test]
+fn reuse_mapped_chunks() {
+    for format in FORMATS {
+        let path = directory("reuse", format);
+        let kernel = open(&path, format);
+        kernel.write(b"old", &data(20_000, 1)[..]).unwrap();
+        assert_eq!(read(&kernel, b"old"), data(20_000, 1));
+
+        kernel.delete(b"old").unwrap();
+        kernel.write(b"new", &data(20_000, 2)[..]).unwrap();
+        assert_eq!(read(&kernel, b"new"), data(20_000, 2));
+        assert!(kernel.read(b"old", Vec::new()).is_err());
+        assert!(kernel.fsck(false).unwrap().is_clean());
+    }
+}
@@ -0,0 +1,104 @@
+use physeter::Kernel;
+use std::io::{ErrorKind, Read, Seek, SeekFrom};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn kernel(name: &str) -> Kernel {
+    let path = std::env::temp_dir().join(format!("physeter-object-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024).unwrap()
+}
+
+#[test]
+fn sequential_read_across_tracks() {
+    let kernel = kernel("sequential");
+    let source = data(3 * 1024 * 1024);
+    kernel.write(b"a", &source[..]).unwrap();
+
+    let mut object = kernel.open(b"a").unwrap();
+    let mut output = Vec::new();
+    let mut buf = [0u8; 1000];
+    loop {
+        match object.read(&mut buf).unwrap() {
+            0 => break,
+            n => output.extend_from_slice(&buf[..n]),
+        }
+    }
+
+    assert_eq!(output, source);
+}
+
+#[test]
+fn random_seek() {
+    let kernel = kernel("random");
+    let source = data(3 * 1024 * 1024);
+    kernel.write(b"a", &source[..]).unwrap();
+
+    let mut object = kernel.open(b"a").unwrap();
+    for offset in [2_000_000u64, 10, 1_048_000, 4086, 4085, 3 * 1024 * 1024 - 5] {
+        object.seek(SeekFrom::Start(offset)).unwrap();
+        let mut buf = vec![0u8; 5000];
+        let mut size = 0;
+        while size < buf.len() {
+            match object.read(&mut buf[size..]).unwrap() {
+                0 => break,
+                n => size += n,
+            }
+        }
+
+        let end = std::cmp::min(offset as usize + 5000, source.len());
+        assert_eq!(&buf[..size], &source[offset as usize..end]);
+    }
+}
+
+#[test]
+fn invalid_seek() {
+    let kernel = kernel("invalid");
+    kernel.write(b"a", &data(10000)[..]).unwrap();
+
+    let mut object = kernel.open(b"a").unwrap();
+    let error = object.seek(SeekFrom::End(-10001)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+    object.seek(SeekFrom::Start(u64::MAX)).unwrap();
+    let error = object.seek(SeekFrom::Current(1)).unwrap_err();
+    assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    assert_eq!(object.stream_position().unwrap(), u64::MAX);
+
+    let mut buf = [0u8; 10];
+    assert_eq!(object.read(&mut buf).unwrap(), 0);
+}
+
+#[test]
+fn object_holds_read_lock() {
+    let kernel = Arc::new(kernel("locked"));
+    let source = data(10000);
+    kernel.write(b"a", &source[..]).unwrap();
+
+    let mut object = kernel.open(b"a").unwrap();
+    let (tx, rx) = mpsc::channel();
+    let deleter = {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.delete(b"a").unwrap();
+            tx.send(()).unwrap();
+        })
+    };
+
+    // 句柄存活期间删除被阻塞，
+    // 分片没有被释放
+    assert!(rx.recv_timeout(Duration::from_millis(300)).is_err());
+    let mut output = Vec::new();
+    object.read_to_end(&mut output).unwrap();
+    assert_eq!(output, source);
+
+    drop(object);
+    rx.recv_timeout(Duration::from_secs(5)).unwrap();
+    deleter.join().unwrap();
+    assert!(kernel.open(b"a").is_err());
+}
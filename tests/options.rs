@@ -0,0 +1,54 @@
+use physeter::{Kernel, KernelOptions};
+use std::path::{Path, PathBuf};
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-options-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn defaults(path: &Path) -> KernelOptions {
+    KernelOptions::from(path.to_str().unwrap().to_string(), 1024 * 1024)
+}
+
+#[test]
+fn invalid_track_size() {
+    let path = directory("track-size");
+    let mut options = defaults(&path);
+    options.track_size = 1024 * 1024 + 1;
+    assert!(Kernel::with_options(options).is_err());
+
+    // 配置无效的时候不会初始化存储目录
+    assert_eq!(std::fs::read_dir(&path).unwrap().count(), 0);
+}
+
+#[test]
+fn invalid_chunk_size() {
+    let path = directory("chunk-size");
+    let mut options = defaults(&path);
+    options.chunk_size = 8;
+    assert!(Kernel::with_options(options).is_err());
+
+    let mut options = defaults(&path);
+    options.chunk_size = 128 * 1024;
+    assert!(Kernel::with_options(options).is_err());
+}
+
+#[test]
+#[cfg(not(feature = "memmap2"))]
+fn mmap_without_feature() {
+    let path = directory("mmap");
+    let mut options = defaults(&path);
+    options.mmap = true;
+    assert!(Kernel::with_options(options).is_err());
+}
+
+#[test]
+fn mmap_with_direct_io() {
+    let path = directory("mmap-direct");
+    let mut options = defaults(&path);
+    options.mmap = true;
+    options.direct_io = true;
+    assert!(Kernel::with_options(options).is_err());
+}
@@ -0,0 +1,132 @@
+use physeter::{Kernel, KernelOptions};
+use std::collections::BTreeMap;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const CHUNK_SIZE: u64 = 4096;
+const TRACK_SIZE: u64 = 1024 * 1024;
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-read-only-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> Kernel {
+    Kernel::new(path.to_str().unwrap().to_string(), TRACK_SIZE).unwrap()
+}
+
+fn open_read_only(path: &Path) -> Kernel {
+    let mut options = KernelOptions::load(path.to_str().unwrap().to_string(), TRACK_SIZE).unwrap();
+    options.read_only = true;
+    Kernel::with_options(options).unwrap()
+}
+
+/// 存储目录中除索引之外的所有文件
+fn snapshot(path: &Path) -> BTreeMap<String, Vec<u8>> {
+    std::fs::read_dir(path)
+        .unwrap()
+        .map(|x| x.unwrap())
+        .filter(|x| x.file_type().unwrap().is_file())
+        .map(|x| (x.file_name().into_string().unwrap(), std::fs::read(x.path()).unwrap()))
+        .collect()
+}
+
+fn record(kind: u8, id: u64, body: &[u8]) -> Vec<u8> {
+    let mut packet = vec![kind];
+    packet.extend_from_slice(&id.to_be_bytes());
+    packet.extend_from_slice(&(body.len() as u32).to_be_bytes());
+    packet.extend_from_slice(body);
+    let crc = crc32c::crc32c(&packet);
+    packet.extend_from_slice(&crc.to_be_bytes());
+    packet
+}
+
+/// 留下一个分配了分片但是没有完成的操作
+fn interrupted(path: &Path) {
+    let track = path.join("1.track");
+    let offset = std::fs::metadata(&track).unwrap().len();
+    let mut file = OpenOptions::new().append(true).open(&track).unwrap();
+    file.write_all(&[0u8; CHUNK_SIZE as usize]).unwrap();
+
+    let mut begin = 1u32.to_be_bytes().to_vec();
+    begin.push(b'c');
+
+    let mut alloc = 1u32.to_be_bytes().to_vec();
+    alloc.extend_from_slice(&1u16.to_be_bytes());
+    alloc.extend_from_slice(&1u32.to_be_bytes());
+    alloc.extend_from_slice(&offset.to_be_bytes());
+
+    let mut journal = OpenOptions::new().append(true).open(path.join("journal")).unwrap();
+    journal.write_all(&record(1, 100, &begin)).unwrap();
+    journal.write_all(&record(4, 100, &alloc)).unwrap();
+}
+
+#[test]
+fn read_only_does_not_modify_store() {
+    let path = directory("store");
+    let kernel = open(&path);
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    kernel.write(b"b", &[2u8; 100][..]).unwrap();
+    drop(kernel);
+
+    interrupted(&path);
+    let before = snapshot(&path);
+
+    let kernel = open_read_only(&path);
+    let mut output = Vec::new();
+    kernel.read(b"a", &mut output).unwrap();
+    assert_eq!(output, vec![1u8; 10000]);
+
+    assert!(kernel.put(b"a", &b"hello"[..]).is_err());
+    assert!(kernel.append(b"b", &b"hello"[..]).is_err());
+    assert!(kernel.delete(b"b").is_err());
+    assert!(kernel.fsck(true).is_err());
+
+    // 没有执行恢复，
+    // 未完成操作分配的分片仍然是泄露的
+    let report = kernel.fsck(false).unwrap();
+    assert_eq!(report.orphans.len(), 1);
+
+    let target = directory("target");
+    let target = open(&target);
+    let report = kernel.migrate(&target, |_| ()).unwrap();
+    assert_eq!(report.objects, 2);
+    drop(kernel);
+
+    assert_eq!(snapshot(&path), before);
+
+    let mut output = Vec::new();
+    target.read(b"b", &mut output).unwrap();
+    assert_eq!(output, vec![2u8; 100]);
+}
+
+#[test]
+fn read_only_legacy_directory() {
+    let path = directory("legacy");
+
+    let mut track = Vec::new();
+    track.extend_from_slice(&0u64.to_be_bytes());
+    track.extend_from_slice(&0u64.to_be_bytes());
+    track.extend_from_slice(&24u64.to_be_bytes());
+    std::fs::write(path.join("1.track"), &track).unwrap();
+
+    let kernel = open_read_only(&path);
+    assert!(kernel.fsck(false).unwrap().is_clean());
+    drop(kernel);
+
+    // 没有写入超级块和日志
+    assert_eq!(snapshot(&path).into_keys().collect::<Vec<_>>(), vec!["1.track"]);
+}
+
+#[test]
+fn read_only_empty_directory() {
+    let path = directory("empty");
+    let kernel = open_read_only(&path);
+    assert!(kernel.write(b"a", &b"hello"[..]).is_err());
+    drop(kernel);
+
+    assert!(snapshot(&path).is_empty());
+}
@@ -0,0 +1,43 @@
+use physeter::{Kernel, ScrubOptions};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+fn kernel(name: &str) -> Kernel {
+    let path = std::env::temp_dir().join(format!("physeter-scrub-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    Kernel::new(path.to_str().unwrap().to_string(), 64 * 1024 * 1024).unwrap()
+}
+
+#[test]
+fn throttled_scrub_does_not_block_writers() {
+    let kernel = Arc::new(kernel("throttled"));
+    kernel.write(b"a", &[1u8; 1024 * 1024][..]).unwrap();
+
+    // 限速之后巡检需要大约两秒
+    let scrub = {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.scrub_with(&ScrubOptions {
+                rate: Some(512 * 1024),
+                ..Default::default()
+            }).unwrap()
+        })
+    };
+
+    std::thread::sleep(Duration::from_millis(200));
+    let (tx, rx) = mpsc::channel();
+    {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.put(b"a", &[2u8; 100_000][..]).unwrap();
+            kernel.write(b"b", &[3u8; 100_000][..]).unwrap();
+            tx.send(()).unwrap();
+        });
+    }
+
+    assert!(rx.recv_timeout(Duration::from_secs(1)).is_ok());
+    let report = scrub.join().unwrap();
+    assert!(report.damaged.is_empty());
+    assert_eq!(report.objects, 1);
+}
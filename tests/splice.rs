@@ -0,0 +1,138 @@
+#![cfg(target_os = "linux")]
+
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::fs::OpenOptions;
+use std::io::Read;
+use std::os::unix::fs::FileExt;
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+fn data(size: usize) -> Vec<u8> {
+    (0..size).map(|i| (i * 31 % 251) as u8).collect()
+}
+
+fn kernel(name: &str) -> Kernel {
+    let path = std::env::temp_dir().join(format!("physeter-splice-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    Kernel::new(path.to_str().unwrap().to_string(), 64 * 1024 * 1024).unwrap()
+}
+
+fn checksummed(path: &Path) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(ChunkFormat::Crc32c)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+/// 通过cat转发发送的数据，
+/// 返回发送结果和收到的数据
+fn transfer(kernel: &Kernel, key: &[u8], offset: u64, len: u64) -> (bool, Vec<u8>) {
+    let mut child = Command::new("cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let reader = std::thread::spawn(move || {
+        let mut output = Vec::new();
+        stdout.read_to_end(&mut output).unwrap();
+        output
+    });
+
+    let result = kernel.splice_range(key, offset, len, &stdin);
+    drop(stdin);
+
+    let output = reader.join().unwrap();
+    child.wait().unwrap();
+    (result.is_ok(), output)
+}
+
+#[test]
+fn splice_range() {
+    let kernel = kernel("range");
+    let source = data(100_000);
+    kernel.write(b"a", &source[..]).unwrap();
+
+    for (offset, len) in [(0u64, u64::MAX), (5000, 10000), (4086, 4086), (99_999, 10), (200_000, 10)] {
+        let start = std::cmp::min(offset as usize, source.len());
+        let end = std::cmp::min(offset.saturating_add(len) as usize, source.len());
+        assert_eq!(transfer(&kernel, b"a", offset, len), (true, source[start..end].to_vec()));
+    }
+}
+
+#[test]
+fn splice_does_not_block_writers() {
+    let kernel = Arc::new(kernel("blocking"));
+    let source = data(1024 * 1024);
+    kernel.write(b"a", &source[..]).unwrap();
+
+    // 不读取输出的时候管道写满，
+    // 发送会一直阻塞
+    let mut child = Command::new("cat")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    let stdin = child.stdin.take().unwrap();
+    let mut stdout = child.stdout.take().unwrap();
+    let sender = {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.splice(b"a", &stdin).unwrap();
+        })
+    };
+
+    std::thread::sleep(Duration::from_millis(200));
+    let (tx, rx) = mpsc::channel();
+    {
+        let kernel = kernel.clone();
+        std::thread::spawn(move || {
+            kernel.write(b"b", &[1u8; 100_000][..]).unwrap();
+            tx.send(()).unwrap();
+        });
+    }
+
+    let written = rx.recv_timeout(Duration::from_secs(5)).is_ok();
+    let mut output = Vec::new();
+    stdout.read_to_end(&mut output).unwrap();
+    sender.join().unwrap();
+    child.wait().unwrap();
+
+    assert!(written);
+    assert_eq!(output, source);
+}
+
+#[test]
+fn splice_verifies_checksums() {
+    let path = std::env::temp_dir().join("physeter-splice-checksum");
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+
+    let kernel = checksummed(&path);
+    let source = data(100_000);
+    kernel.write(b"a", &source[..]).unwrap();
+    assert_eq!(transfer(&kernel, b"a", 0, u64::MAX), (true, source.clone()));
+    assert_eq!(transfer(&kernel, b"a", 5000, 10000), (true, source[5000..15000].to_vec()));
+
+    // 损坏第二个分片的数据，
+    // 范围覆盖损坏分片的时候返回错误
+    let file = OpenOptions::new().write(true).open(path.join("1.track")).unwrap();
+    file.write_all_at(&[0xAB; 16], 4096 + 8192 + 100).unwrap();
+    drop(kernel);
+
+    let kernel = checksummed(&path);
+
+    assert!(!transfer(&kernel, b"a", 0, u64::MAX).0);
+    assert!(!transfer(&kernel, b"a", 5000, 10).0);
+    assert_eq!(transfer(&kernel, b"a", 0, 100), (true, source[..100].to_vec()));
+}
@@ -0,0 +1,153 @@
+use physeter::{ChunkFormat, Kernel, KernelOptions};
+use std::fs::OpenOptions;
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+
+const SLOT_SIZE: u64 = 4096;
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-header-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path) -> anyhow::Result<Kernel> {
+    Kernel::new(path.to_str().unwrap().to_string(), 1024 * 1024)
+}
+
+fn read(kernel: &Kernel, key: &[u8]) -> Vec<u8> {
+    let mut output = Vec::new();
+    kernel.read(key, &mut output).unwrap();
+    output
+}
+
+/// 槽中的序号位于魔数，版本，轨道ID和分片长度之后
+fn seq(path: &Path, slot: u64) -> u64 {
+    let file = std::fs::File::open(path.join("1.track")).unwrap();
+    let mut buffer = [0u8; 8];
+    file.read_exact_at(&mut buffer, slot * SLOT_SIZE + 22).unwrap();
+    u64::from_be_bytes(buffer)
+}
+
+fn corrupt(path: &Path, slot: u64) {
+    let file = OpenOptions::new().write(true).open(path.join("1.track")).unwrap();
+    file.write_all_at(&[0xff; 64], slot * SLOT_SIZE).unwrap();
+}
+
+#[test]
+fn older_slot_corrupted() {
+    let path = directory("older");
+    let kernel = open(&path).unwrap();
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    drop(kernel);
+
+    let older = if seq(&path, 0) < seq(&path, 1) { 0 } else { 1 };
+    corrupt(&path, older);
+
+    let kernel = open(&path).unwrap();
+    assert_eq!(read(&kernel, b"a"), vec![1u8; 10000]);
+    assert!(kernel.fsck(false).unwrap().is_clean());
+}
+
+#[test]
+fn first_slot_corrupted() {
+    let path = directory("first");
+    let kernel = open(&path).unwrap();
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    drop(kernel);
+
+    // 第一个槽的魔数损坏的时候不能按照旧版本文件头读取
+    corrupt(&path, 0);
+
+    let kernel = open(&path).unwrap();
+    assert_eq!(read(&kernel, b"a"), vec![1u8; 10000]);
+    kernel.write(b"b", &[2u8; 10000][..]).unwrap();
+    assert_eq!(read(&kernel, b"b"), vec![2u8; 10000]);
+}
+
+#[test]
+fn both_slots_corrupted() {
+    let path = directory("both");
+    let kernel = open(&path).unwrap();
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    drop(kernel);
+
+    corrupt(&path, 0);
+    corrupt(&path, 1);
+    assert!(open(&path).is_err());
+}
+
+/// 写入旧版本轨道，
+/// 旧版本文件头由失效链表头部，尾部以及数据长度组成，
+/// 之后是指定数量的4KB分片
+fn legacy(path: &Path, free: u64, chunks: u64) {
+    let size = 24 + chunks * 4096;
+    let mut track = Vec::new();
+    track.extend_from_slice(&free.to_be_bytes());
+    track.extend_from_slice(&free.to_be_bytes());
+    track.extend_from_slice(&size.to_be_bytes());
+    track.resize(size as usize, 0);
+    std::fs::write(path.join("1.track"), &track).unwrap();
+}
+
+#[test]
+fn legacy_header() {
+    let path = directory("legacy");
+    legacy(&path, 0, 1);
+
+    let kernel = open(&path).unwrap();
+    kernel.write(b"a", &[1u8; 10000][..]).unwrap();
+    assert_eq!(read(&kernel, b"a"), vec![1u8; 10000]);
+    assert!(path.join("superblock").exists());
+}
+
+#[test]
+fn legacy_track_size_mismatch() {
+    let path = directory("legacy-track-size");
+    legacy(&path, 0, 3);
+
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(3 * 4096)
+        .build()
+        .unwrap();
+
+    assert!(Kernel::with_options(options).is_err());
+    assert!(!path.join("superblock").exists());
+}
+
+#[test]
+fn legacy_chunk_format_mismatch() {
+    let path = directory("legacy-chunk-format");
+    legacy(&path, 0, 1);
+
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_format(ChunkFormat::Crc32c)
+        .build()
+        .unwrap();
+
+    assert!(Kernel::with_options(options).is_err());
+    assert!(!path.join("superblock").exists());
+}
+
+#[test]
+fn legacy_chunk_size_mismatch() {
+    let path = directory("legacy-chunk-size");
+
+    // 两个4KB分片的长度刚好是一个8KB分片，
+    // 失效链表指向第二个分片，按照8KB分片无法对齐
+    legacy(&path, 24 + 4096, 2);
+
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .chunk_size(8192)
+        .build()
+        .unwrap();
+
+    assert!(Kernel::with_options(options).is_err());
+    assert!(!path.join("superblock").exists());
+}
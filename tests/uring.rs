@@ -0,0 +1,120 @@
+#![cfg(all(feature = "io-uring", target_os = "linux"))]
+
+use physeter::{Kernel, KernelOptions};
+use std::path::{Path, PathBuf};
+
+fn data(size: usize, seed: usize) -> Vec<u8> {
+    (0..size).map(|i| ((i + seed) * 31 % 251) as u8).collect()
+}
+
+fn directory(name: &str) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("physeter-uring-{}", name));
+    let _ = std::fs::remove_dir_all(&path);
+    std::fs::create_dir_all(&path).unwrap();
+    path
+}
+
+fn open(path: &Path, direct: bool) -> Kernel {
+    let options = KernelOptions::builder()
+        .path(path.to_str().unwrap())
+        .track_size(1024 * 1024)
+        .direct_io(direct)
+        .build()
+        .unwrap();
+
+    Kernel::with_options(options).unwrap()
+}
+
+fn filter(code: u16, k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+    libc::sock_filter { code, jt, jf, k }
+}
+
+/// 禁止当前线程创建io_uring
+///
+/// 过滤器只作用于当前线程以及之后创建的子线程，
+/// `io_uring_setup`返回`ENOSYS`，
+/// 和内核不支持io_uring的情况一致
+fn disable_uring() {
+    let filter = [
+        filter((libc::BPF_LD | libc::BPF_W | libc::BPF_ABS) as u16, 0, 0, 0),
+        filter((libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16, libc::SYS_io_uring_setup as u32, 0, 1),
+        filter((libc::BPF_RET | libc::BPF_K) as u16, libc::SECCOMP_RET_ERRNO | libc::ENOSYS as u32, 0, 0),
+        filter((libc::BPF_RET | libc::BPF_K) as u16, libc::SECCOMP_RET_ALLOW, 0, 0),
+    ];
+
+    let program = libc::sock_fprog {
+        len: filter.len() as u16,
+        filter: filter.as_ptr() as *mut _,
+    };
+
+    unsafe {
+        assert_eq!(libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0), 0);
+        assert_eq!(libc::prctl(libc::PR_SET_SECCOMP, libc::SECCOMP_MODE_FILTER, &program), 0);
+    }
+
+    let mut params = [0u8; 120];
+    let result = unsafe { libc::syscall(libc::SYS_io_uring_setup, 8, params.as_mut_ptr()) };
+    assert_eq!(result, -1);
+    assert_eq!(std::io::Error::last_os_error().raw_os_error(), Some(libc::ENOSYS));
+}
+
+/// 覆盖批量写入，批量读取，追加以及跨越轨道的读写
+fn round_trip(path: &Path, direct: bool) {
+    let kernel = open(path, direct);
+    let sizes = [1, 4086, 100_000, 1_500_000];
+    for (index, size) in sizes.iter().enumerate() {
+        kernel.write(format!("{}", index).as_bytes(), &data(*size, index)[..]).unwrap();
+    }
+
+    let mut expected = data(5000, 9);
+    kernel.write(b"log", &expected[..]).unwrap();
+    for size in [10, 70_000] {
+        let chunk = data(size, size);
+        kernel.append(b"log", &chunk[..]).unwrap();
+        expected.extend_from_slice(&chunk);
+    }
+
+    kernel.delete(b"0").unwrap();
+    drop(kernel);
+
+    let kernel = open(path, direct);
+    for (index, size) in sizes.iter().enumerate().skip(1) {
+        let mut output = Vec::new();
+        kernel.read(format!("{}", index).as_bytes(), &mut output).unwrap();
+        assert_eq!(output, data(*size, index));
+    }
+
+    let mut output = Vec::new();
+    kernel.read(b"log", &mut output).unwrap();
+    assert_eq!(output, expected);
+
+    let mut output = Vec::new();
+    kernel.read_range(b"3", 1_000_000, 100_000, &mut output).unwrap();
+    assert_eq!(output, &data(1_500_000, 3)[1_000_000..1_100_000]);
+    assert!(kernel.read(b"0", Vec::new()).is_err());
+    assert!(kernel.fsck(false).unwrap().is_clean());
+}
+
+#[test]
+fn fallback_without_uring() {
+    let path = directory("fallback");
+    std::thread::spawn(move || {
+        disable_uring();
+        round_trip(&path, false);
+    }).join().unwrap();
+}
+
+#[test]
+fn fallback_without_uring_direct() {
+    let path = directory("fallback-direct");
+    std::thread::spawn(move || {
+        disable_uring();
+        round_trip(&path, true);
+    }).join().unwrap();
+}
+
+#[test]
+fn uring_round_trip() {
+    round_trip(&directory("ring"), false);
+    round_trip(&directory("ring-direct"), true);
+}